@@ -0,0 +1,239 @@
+use bitcoin::opcodes::all::*;
+use bitcoin::script::{self, Instruction, Script};
+
+use crate::{ExecCtx, MinimalPushError};
+
+/// The number of signatures an OP_CHECKMULTISIG counts for when the number of
+/// public keys is not known statically.
+const MAX_SIGOPS_PER_MULTISIG: usize = 20;
+
+/// Check that all data pushes that could be interpreted as a number
+/// are minimally encoded.
+///
+/// Every push of 4 bytes or fewer is checked. On the first violation, or
+/// the first instruction that fails to parse, the index of the offending
+/// instruction is returned together with the error.
+pub fn check_minimal_pushes(script: &Script) -> Result<(), (usize, MinimalPushError)> {
+    for (idx, ins) in script.instructions().enumerate() {
+        let push = match ins {
+            Ok(Instruction::PushBytes(p)) => p,
+            Ok(Instruction::Op(_)) => continue,
+            Err(e) => return Err((idx, MinimalPushError::InvalidScript(e))),
+        };
+        if push.len() <= 4 {
+            script::read_scriptint_size(push.as_bytes(), 4, true)
+                .map_err(|e| (idx, MinimalPushError::NonMinimal(e)))?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that the OP_IF/OP_NOTIF, OP_ELSE and OP_ENDIF structure of the
+/// script is balanced.
+///
+/// On failure, the index of the first unbalanced or unparsable instruction
+/// is returned, or the number of instructions if an OP_IF is never closed.
+/// Conditionals count the same whether or not their branch would be executed.
+pub fn check_conditional_balance(script: &Script) -> Result<(), usize> {
+    let mut depth = 0;
+    let mut nb_instructions = 0;
+    for (idx, ins) in script.instructions().enumerate() {
+        nb_instructions += 1;
+        match ins {
+            Ok(Instruction::Op(OP_IF)) | Ok(Instruction::Op(OP_NOTIF)) => depth += 1,
+            Ok(Instruction::Op(OP_ELSE)) if depth == 0 => return Err(idx),
+            Ok(Instruction::Op(OP_ENDIF)) => {
+                if depth == 0 {
+                    return Err(idx);
+                }
+                depth -= 1;
+            }
+            Ok(_) => {}
+            Err(_) => return Err(idx),
+        }
+    }
+
+    if depth != 0 {
+        Err(nb_instructions)
+    } else {
+        Ok(())
+    }
+}
+
+/// A static estimate of the cost of a script, see [estimate].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Estimate {
+    /// The number of non-push opcodes, as counted by [crate::Options::max_ops].
+    /// Always zero in Tapscript, where opcodes are not counted.
+    pub opcode_count: usize,
+    /// The number of signature checks, following the consensus sigop counting.
+    pub sigop_count: usize,
+    /// The size in bytes of the largest data push.
+    pub max_push_size: usize,
+}
+
+/// Estimate the cost of a script without executing it.
+///
+/// All branches are counted so the result is an upper bound. An
+/// OP_CHECKMULTISIG counts for its number of public keys, both as sigops and
+/// as opcodes, if it directly follows OP_1 to OP_16, and for 20 otherwise. In Tapscript, where
+/// OP_CHECKMULTISIG is disabled, OP_CHECKSIGADD counts instead. Estimation
+/// stops at the first instruction that fails to parse.
+pub fn estimate(script: &Script, ctx: ExecCtx) -> Estimate {
+    let mut ret = Estimate::default();
+    let mut last_op = None;
+    for ins in script.instructions() {
+        let op = match ins {
+            Ok(Instruction::PushBytes(p)) => {
+                ret.max_push_size = ret.max_push_size.max(p.len());
+                last_op = None;
+                continue;
+            }
+            Ok(Instruction::Op(op)) => op,
+            Err(_) => break,
+        };
+
+        if ctx != ExecCtx::Tapscript && op.to_u8() > OP_PUSHNUM_16.to_u8() {
+            ret.opcode_count += 1;
+        }
+        match op {
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => ret.sigop_count += 1,
+            OP_CHECKSIGADD if ctx == ExecCtx::Tapscript => ret.sigop_count += 1,
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY if ctx != ExecCtx::Tapscript => {
+                let nb_keys = match last_op {
+                    Some(n) if n >= OP_PUSHNUM_1.to_u8() && n <= OP_PUSHNUM_16.to_u8() => {
+                        (n - (OP_PUSHNUM_1.to_u8() - 1)) as usize
+                    }
+                    _ => MAX_SIGOPS_PER_MULTISIG,
+                };
+                // Each public key also counts towards the opcode limit.
+                ret.opcode_count += nb_keys;
+                ret.sigop_count += nb_keys;
+            }
+            _ => {}
+        }
+        last_op = Some(op.to_u8());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::script::ScriptIntError::NonMinimalPush;
+
+    use crate::tests::{new_exec, ops, push_bytes};
+    use crate::Options;
+
+    fn push_script(pushes: &[&[u8]]) -> script::ScriptBuf {
+        pushes
+            .iter()
+            .fold(script::Builder::new(), |b, p| b.push_slice(push_bytes(p)))
+            .into_script()
+    }
+
+    #[test]
+    fn minimal_pushes() {
+        assert!(check_minimal_pushes(&push_script(&[&[0x01], &[0xff, 0x00]])).is_ok());
+        // Pushes longer than 4 bytes can't be numbers and are not checked.
+        assert!(check_minimal_pushes(&push_script(&[&[0x01, 0, 0, 0, 0]])).is_ok());
+
+        let res = check_minimal_pushes(&push_script(&[&[0x80]]));
+        assert!(matches!(res, Err((0, MinimalPushError::NonMinimal(NonMinimalPush)))));
+
+        let script = script::Builder::new()
+            .push_opcode(OP_DUP)
+            .push_slice(push_bytes(&[0x01, 0x00]))
+            .into_script();
+        let res = check_minimal_pushes(&script);
+        assert!(matches!(res, Err((1, MinimalPushError::NonMinimal(NonMinimalPush)))));
+
+        // A truncated push doesn't pass.
+        let script = script::ScriptBuf::from_bytes(vec![OP_DUP.to_u8(), OP_PUSHBYTES_2.to_u8(), 1]);
+        let res = check_minimal_pushes(&script);
+        assert!(matches!(res, Err((1, MinimalPushError::InvalidScript(_)))));
+    }
+
+    #[test]
+    fn conditional_balance() {
+        let nested = [OP_IF, OP_NOTIF, OP_ELSE, OP_ENDIF, OP_ELSE, OP_ENDIF];
+        assert_eq!(check_conditional_balance(&ops(&nested)), Ok(()));
+        assert_eq!(check_conditional_balance(&ops(&[OP_NOP])), Ok(()));
+
+        let dangling = [OP_IF, OP_ENDIF, OP_ENDIF];
+        assert_eq!(check_conditional_balance(&ops(&dangling)), Err(2));
+        let unterminated = [OP_NOP, OP_IF, OP_NOP];
+        assert_eq!(check_conditional_balance(&ops(&unterminated)), Err(3));
+        let stray_else = [OP_NOP, OP_ELSE];
+        assert_eq!(check_conditional_balance(&ops(&stray_else)), Err(1));
+    }
+
+    #[test]
+    fn estimate_legacy() {
+        let script = script::Builder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(push_bytes(&[0; 20]))
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_PUSHNUM_2)
+            .push_slice(push_bytes(&[2; 33]))
+            .push_slice(push_bytes(&[3; 33]))
+            .push_slice(push_bytes(&[4; 33]))
+            .push_opcode(OP_PUSHNUM_3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .push_opcode(OP_CHECKMULTISIGVERIFY)
+            .into_script();
+        // 1 for OP_CHECKSIG, 3 for the first and 20 for the second multisig,
+        // which also count as opcodes.
+        let expected = Estimate {
+            opcode_count: 6 + 3 + 20,
+            sigop_count: 24,
+            max_push_size: 33,
+        };
+        assert_eq!(estimate(&script, ExecCtx::Legacy), expected);
+        assert_eq!(estimate(&script, ExecCtx::SegwitV0), expected);
+
+        // The estimate is an upper bound of what execution counts.
+        let script = script::Builder::new()
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_PUSHNUM_2)
+            .push_slice(push_bytes(&[2; 33]))
+            .push_slice(push_bytes(&[3; 33]))
+            .push_slice(push_bytes(&[4; 33]))
+            .push_opcode(OP_PUSHNUM_3)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        assert_eq!(estimate(&script, ExecCtx::Legacy).opcode_count, 4);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        exec.run();
+        assert_eq!(exec.stats().opcode_count, 4);
+    }
+
+    #[test]
+    fn estimate_tapscript() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[1; 32]))
+            .push_opcode(OP_CHECKSIG)
+            .push_slice(push_bytes(&[2; 32]))
+            .push_opcode(OP_CHECKSIGADD)
+            .push_opcode(OP_PUSHNUM_2)
+            .push_opcode(OP_NUMEQUAL)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        let expected = Estimate {
+            opcode_count: 0,
+            sigop_count: 2,
+            max_push_size: 32,
+        };
+        assert_eq!(estimate(&script, ExecCtx::Tapscript), expected);
+
+        let legacy = estimate(&script, ExecCtx::Legacy);
+        assert_eq!(legacy.opcode_count, 4 + MAX_SIGOPS_PER_MULTISIG);
+        // OP_CHECKSIGADD doesn't count and OP_CHECKMULTISIG doesn't follow a number.
+        assert_eq!(legacy.sigop_count, 1 + MAX_SIGOPS_PER_MULTISIG);
+    }
+}
@@ -0,0 +1,72 @@
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+
+use crate::*;
+
+/// Everything needed to create and run an [Exec].
+#[derive(Debug, Clone)]
+pub struct ExecJob {
+    pub ctx: ExecCtx,
+    pub opt: Options,
+    pub tx: TxTemplate,
+    pub script: ScriptBuf,
+    pub script_witness: Vec<Vec<u8>>,
+}
+
+impl ExecJob {
+    /// Run the script to completion.
+    ///
+    /// Returns the execution statistics if the script succeeded.
+    pub fn run(&self) -> Result<ExecStats, Error> {
+        let exec = Exec::new(
+            self.ctx,
+            self.opt.clone(),
+            self.tx.clone(),
+            self.script.clone(),
+            self.script_witness.clone(),
+        )?;
+        crate::run_to_stats(exec)
+    }
+}
+
+/// Run many independent jobs in parallel.
+///
+/// The results are in the same order as the jobs.
+#[cfg(feature = "rayon")]
+pub fn exec_batch(jobs: &[ExecJob]) -> Vec<Result<ExecStats, Error>> {
+    jobs.par_iter().map(|job| job.run()).collect()
+}
+
+#[cfg(all(test, feature = "rayon"))]
+mod tests {
+    use super::*;
+
+    use crate::tests::{ops, tx_template};
+
+    #[test]
+    fn batch_matches_sequential() {
+        let job = |ctx: ExecCtx, script: ScriptBuf| ExecJob {
+            ctx: ctx,
+            opt: Options::default(),
+            tx: tx_template(),
+            script: script,
+            script_witness: vec![],
+        };
+        let jobs = (0..32)
+            .flat_map(|_| {
+                vec![
+                    job(ExecCtx::Legacy, ops(&[OP_PUSHNUM_1, OP_DUP, OP_ADD])),
+                    job(ExecCtx::SegwitV0, ops(&[OP_PUSHNUM_1, OP_DUP])),
+                    job(ExecCtx::Tapscript, ops(&[OP_PUSHNUM_1, OP_VERIFY])),
+                    job(ExecCtx::Legacy, ops(&[OP_DROP])),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let sequential = jobs.iter().map(|j| j.run()).collect::<Vec<_>>();
+        assert_eq!(exec_batch(&jobs), sequential);
+        assert!(sequential[0].is_ok());
+        assert_eq!(sequential[1], Err(Error::Exec(ExecError::CleanStack)));
+        assert_eq!(sequential[3], Err(Error::Exec(ExecError::InvalidStackOperation)));
+    }
+}
@@ -0,0 +1,101 @@
+use bitcoin::hex::FromHex;
+use bitcoin::opcodes::Opcode;
+use bitcoin::script::{Builder, PushBytes, ScriptBuf};
+
+use crate::AsmBuilderError;
+
+/// A script builder that mixes opcodes, data pushes and ASM fragments.
+///
+/// ASM fragments are parsed with [ScriptBuf::parse_asm], so they accept the
+/// same syntax.
+#[derive(Clone)]
+pub struct AsmBuilder(Builder);
+
+impl AsmBuilder {
+    pub fn new() -> AsmBuilder {
+        AsmBuilder(Builder::new())
+    }
+
+    /// Append an opcode.
+    pub fn op(self, op: Opcode) -> AsmBuilder {
+        AsmBuilder(self.0.push_opcode(op))
+    }
+
+    /// Push a number, using OP_0 to OP_16 and OP_1NEGATE where possible.
+    pub fn push_int(self, n: i64) -> AsmBuilder {
+        AsmBuilder(self.0.push_int(n))
+    }
+
+    /// Push raw bytes.
+    pub fn push_slice(self, data: &[u8]) -> AsmBuilder {
+        let data = <&PushBytes>::try_from(data).expect("pushes can't be longer than 4 GiB");
+        AsmBuilder(self.0.push_slice(data))
+    }
+
+    /// Push the bytes given in hex.
+    pub fn push_hex(self, hex: &str) -> Result<AsmBuilder, AsmBuilderError> {
+        let data = Vec::<u8>::from_hex(hex).map_err(AsmBuilderError::InvalidHex)?;
+        Ok(self.push_slice(&data))
+    }
+
+    /// Push the bytes of an ASCII or UTF-8 string.
+    pub fn push_str(self, s: &str) -> AsmBuilder {
+        self.push_slice(s.as_bytes())
+    }
+
+    /// Append the instructions of an ASM fragment.
+    pub fn asm(self, asm: &str) -> Result<AsmBuilder, AsmBuilderError> {
+        let fragment = ScriptBuf::parse_asm(asm).map_err(AsmBuilderError::Asm)?;
+        let mut bytes = self.0.into_script().into_bytes();
+        bytes.extend_from_slice(fragment.as_bytes());
+        Ok(AsmBuilder(Builder::from(bytes)))
+    }
+
+    /// Finish building and return the script.
+    pub fn build(self) -> ScriptBuf {
+        self.0.into_script()
+    }
+}
+
+impl Default for AsmBuilder {
+    fn default() -> Self {
+        AsmBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::opcodes::all::*;
+
+    #[test]
+    fn same_as_parse_asm() {
+        let built = AsmBuilder::new()
+            .op(OP_DUP)
+            .push_int(5)
+            .push_hex("deadbeef")
+            .unwrap()
+            .asm("OP_HASH160 OP_EQUAL")
+            .unwrap()
+            .op(OP_VERIFY)
+            .build();
+        let parsed = ScriptBuf::parse_asm("OP_DUP 5 deadbeef OP_HASH160 OP_EQUAL OP_VERIFY");
+        assert_eq!(built, parsed.unwrap());
+    }
+
+    #[test]
+    fn push_str() {
+        let built = AsmBuilder::new().push_str("tag").build();
+        assert_eq!(built, AsmBuilder::new().push_slice(b"tag").build());
+        assert_eq!(built.as_bytes(), &[OP_PUSHBYTES_3.to_u8(), b't', b'a', b'g']);
+    }
+
+    #[test]
+    fn errors() {
+        let res = AsmBuilder::new().push_hex("xyz");
+        assert!(matches!(res, Err(AsmBuilderError::InvalidHex(_))));
+        let res = AsmBuilder::new().asm("OP_DUP OP_NOT_AN_OPCODE");
+        assert!(matches!(res, Err(AsmBuilderError::Asm(_))));
+    }
+}
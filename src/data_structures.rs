@@ -15,6 +15,15 @@ pub enum StackEntry {
 #[derive(Clone, Eq, Debug, PartialEq)]
 pub struct Stack(Vec<StackEntry>);
 
+/// The changes made to a stack by a single execution step.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StackDelta {
+    /// The number of items removed from the top of the stack.
+    pub popped: usize,
+    /// The items pushed on top of the stack, bottom first.
+    pub pushed: Vec<Vec<u8>>,
+}
+
 impl Stack {
     pub fn new() -> Self {
         Self(Vec::with_capacity(1000))
@@ -54,16 +63,28 @@ impl Stack {
     }
 
     pub fn topnum(&self, offset: isize, require_minimal: bool) -> Result<i64, ExecError> {
+        self.topnum_size(offset, 4, require_minimal)
+    }
+
+    /// Like [Stack::topnum], but allowing numbers of up to [size] bytes.
+    pub fn topnum_size(
+        &self,
+        offset: isize,
+        size: usize,
+        require_minimal: bool,
+    ) -> Result<i64, ExecError> {
         let entry = self.top(offset)?;
         match entry {
             StackEntry::Num(v) => {
-                if *v <= i32::MAX as i64 {
+                if fits_scriptint(*v, size) {
                     Ok(*v)
                 } else {
                     Err(ExecError::ScriptIntNumericOverflow)
                 }
             }
-            StackEntry::StrRef(v) => Ok(read_scriptint(v.borrow().as_slice(), 4, require_minimal)?),
+            StackEntry::StrRef(v) => {
+                Ok(read_scriptint(v.borrow().as_slice(), size, require_minimal)?)
+            }
         }
     }
 
@@ -111,7 +132,7 @@ impl Stack {
         let entry = self.0.pop().ok_or(ExecError::InvalidStackOperation)?;
         match entry {
             StackEntry::Num(v) => {
-                if v <= i32::MAX as i64 {
+                if fits_scriptint(v, 4) {
                     Ok(v)
                 } else {
                     Err(ExecError::ScriptIntNumericOverflow)
@@ -142,6 +163,35 @@ impl Stack {
             StackEntry::StrRef(v) => v.borrow().to_vec(),
         }
     }
+
+    /// Compute the changes that turned the [before] stack into this one.
+    ///
+    /// Items that were only reordered, like by OP_SWAP, are reported
+    /// as popped and pushed again.
+    pub fn delta_from(&self, before: &Stack) -> StackDelta {
+        let common = self
+            .0
+            .iter()
+            .zip(before.0.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        StackDelta {
+            popped: before.len() - common,
+            pushed: (common..self.len()).map(|i| self.get(i)).collect(),
+        }
+    }
+}
+
+/// Whether the number can be encoded as a script number of at most [size] bytes.
+///
+/// Only zero, which is encoded as the empty item, fits in zero bytes.
+fn fits_scriptint(v: i64, size: usize) -> bool {
+    match v.checked_abs() {
+        Some(0) => true,
+        Some(_) if size == 0 => false,
+        Some(abs) => size >= 8 || abs < 1 << (8 * size - 1),
+        None => false,
+    }
 }
 
 impl Default for Stack {
@@ -149,3 +199,30 @@ impl Default for Stack {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scriptint_sizes() {
+        assert!(fits_scriptint(0, 0));
+        assert!(!fits_scriptint(1, 0));
+        assert!(!fits_scriptint(-1, 0));
+        assert!(fits_scriptint(127, 1));
+        assert!(!fits_scriptint(128, 1));
+        assert!(fits_scriptint(-0x7fffffff, 4));
+        assert!(!fits_scriptint(0x80000000, 4));
+        assert!(fits_scriptint(i64::MAX, 8));
+        assert!(!fits_scriptint(i64::MIN, 8));
+    }
+
+    #[test]
+    fn topnum_size_zero() {
+        let mut stack = Stack::new();
+        stack.pushnum(0);
+        assert_eq!(stack.topnum_size(-1, 0, true), Ok(0));
+        stack.pushnum(1);
+        assert_eq!(stack.topnum_size(-1, 0, true), Err(ExecError::ScriptIntNumericOverflow));
+    }
+}
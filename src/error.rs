@@ -1,9 +1,14 @@
 use bitcoin::blockdata::script;
+use bitcoin::hex::HexToBytesError;
 
 /// Error of a script execution.
 ///
 /// Equivalent to Bitcoin Core's `ScriptError_t`.
+///
+/// With the `serde` feature, variants are serialized as their name,
+/// f.e. `"InvalidStackOperation"`. These names are stable.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ExecError {
     DisabledOpcode,
     OpCodeseparator,
@@ -15,30 +20,262 @@ pub enum ExecError {
     NegativeLocktime,
     UnsatisfiedLocktime,
     UnbalancedConditional,
+    MinimalIf,
     TapscriptMinimalIf,
     Verify,
     OpReturn,
     EqualVerify,
     NumEqualVerify,
     CheckSigVerify,
+    CheckMultiSigVerify,
     TapscriptValidationWeight,
+    SigHashtype,
+    SigDer,
+    SigHighS,
+    SigNullDummy,
     PubkeyType,
     SchnorrSigSize,
     SchnorrSigHashtype,
     SchnorrSig,
     TapscriptCheckMultiSig,
     PubkeyCount,
+    SigCount,
     StackSize,
+    CleanStack,
+    WitnessProgramMismatch,
     WitnessPubkeyType,
+    EvalFalse,
 
     // new ones for us
     ScriptIntNumericOverflow,
     Debug,
+    OutOfGas,
+}
+
+/// The category of an [ExecError], given by the hundreds digit of its code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ErrorCategory {
+    /// 1xx: invalid stack operations and final stack checks.
+    Stack,
+    /// 2xx: script number encoding and arithmetic.
+    Numeric,
+    /// 3xx: signatures and public keys.
+    Signature,
+    /// 4xx: absolute and relative locktimes.
+    Locktime,
+    /// 5xx: opcodes, conditionals and verification opcodes.
+    Script,
+    /// 6xx: resource limits.
+    Limit,
+    /// 9xx: everything else.
+    Other,
+}
+
+impl ExecError {
+    /// A stable numeric code for this error.
+    ///
+    /// The hundreds digit gives the [ErrorCategory]. Codes are never
+    /// changed or reused, also not when a variant is removed.
+    pub fn code(&self) -> u32 {
+        match self {
+            ExecError::InvalidStackOperation => 100,
+            ExecError::CleanStack => 101,
+            ExecError::EvalFalse => 102,
+
+            ExecError::MinimalData => 200,
+            ExecError::ScriptIntNumericOverflow => 201,
+
+            ExecError::CheckSigVerify => 300,
+            ExecError::PubkeyType => 301,
+            ExecError::SchnorrSigSize => 302,
+            ExecError::SchnorrSigHashtype => 303,
+            ExecError::SchnorrSig => 304,
+            ExecError::TapscriptCheckMultiSig => 305,
+            ExecError::PubkeyCount => 306,
+            ExecError::WitnessPubkeyType => 307,
+            ExecError::SigHashtype => 308,
+            ExecError::SigDer => 309,
+            ExecError::SigHighS => 310,
+            ExecError::CheckMultiSigVerify => 311,
+            ExecError::SigCount => 312,
+            ExecError::SigNullDummy => 313,
+
+            ExecError::NegativeLocktime => 400,
+            ExecError::UnsatisfiedLocktime => 401,
+
+            ExecError::DisabledOpcode => 500,
+            ExecError::OpCodeseparator => 501,
+            ExecError::BadOpcode => 502,
+            ExecError::UnbalancedConditional => 503,
+            ExecError::MinimalIf => 504,
+            ExecError::TapscriptMinimalIf => 505,
+            ExecError::Verify => 506,
+            ExecError::OpReturn => 507,
+            ExecError::EqualVerify => 508,
+            ExecError::NumEqualVerify => 509,
+
+            ExecError::OpCount => 600,
+            ExecError::PushSize => 601,
+            ExecError::StackSize => 602,
+            ExecError::TapscriptValidationWeight => 603,
+            ExecError::OutOfGas => 604,
+
+            ExecError::WitnessProgramMismatch => 900,
+            ExecError::Debug => 901,
+        }
+    }
+
+    /// The category of this error, derived from its [ExecError::code].
+    pub fn category(&self) -> ErrorCategory {
+        match self.code() / 100 {
+            1 => ErrorCategory::Stack,
+            2 => ErrorCategory::Numeric,
+            3 => ErrorCategory::Signature,
+            4 => ErrorCategory::Locktime,
+            5 => ErrorCategory::Script,
+            6 => ErrorCategory::Limit,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
+/// Error parsing a script from hex, see [crate::parse_hex_script].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseHexError {
+    /// The string is not valid hex.
+    InvalidHex(HexToBytesError),
+    /// The push at byte offset [offset] declares more bytes than remain.
+    TruncatedPush { offset: usize },
+    /// The instruction at byte offset [offset] is invalid.
+    InvalidScript { offset: usize, error: script::Error },
+}
+
+/// Error of [crate::check_minimal_pushes].
+#[derive(Debug)]
+pub enum MinimalPushError {
+    /// A push that could be a number is not minimally encoded.
+    NonMinimal(script::ScriptIntError),
+    /// The instruction failed to parse.
+    InvalidScript(script::Error),
+}
+
+/// Error building a script with an [crate::AsmBuilder].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmBuilderError {
+    /// An ASM fragment failed to parse.
+    Asm(script::ParseAsmError),
+    /// A hex push is not valid hex.
+    InvalidHex(HexToBytesError),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Error {
     Exec(ExecError),
     InvalidScript(script::Error),
+    ParseHex(ParseHexError),
     Other(&'static str),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn serde_variant_names() {
+        let json = serde_json::to_string(&ExecError::InvalidStackOperation).unwrap();
+        assert_eq!(json, "\"InvalidStackOperation\"");
+
+        // Every variant is serialized as its name and read back from it.
+        for err in ALL.iter() {
+            let json = serde_json::to_string(err).unwrap();
+            assert_eq!(json, format!("\"{:?}\"", err));
+            assert_eq!(serde_json::from_str::<ExecError>(&json).unwrap(), *err);
+        }
+        let categories = [
+            ErrorCategory::Stack,
+            ErrorCategory::Numeric,
+            ErrorCategory::Signature,
+            ErrorCategory::Locktime,
+            ErrorCategory::Script,
+            ErrorCategory::Limit,
+            ErrorCategory::Other,
+        ];
+        for cat in categories.iter() {
+            let json = serde_json::to_string(cat).unwrap();
+            assert_eq!(json, format!("\"{:?}\"", cat));
+            assert_eq!(serde_json::from_str::<ErrorCategory>(&json).unwrap(), *cat);
+        }
+    }
+
+    const ALL: [ExecError; 38] = [
+        ExecError::DisabledOpcode,
+        ExecError::OpCodeseparator,
+        ExecError::BadOpcode,
+        ExecError::OpCount,
+        ExecError::PushSize,
+        ExecError::MinimalData,
+        ExecError::InvalidStackOperation,
+        ExecError::NegativeLocktime,
+        ExecError::UnsatisfiedLocktime,
+        ExecError::UnbalancedConditional,
+        ExecError::MinimalIf,
+        ExecError::TapscriptMinimalIf,
+        ExecError::Verify,
+        ExecError::OpReturn,
+        ExecError::EqualVerify,
+        ExecError::NumEqualVerify,
+        ExecError::CheckSigVerify,
+        ExecError::CheckMultiSigVerify,
+        ExecError::TapscriptValidationWeight,
+        ExecError::SigHashtype,
+        ExecError::SigDer,
+        ExecError::SigHighS,
+        ExecError::SigNullDummy,
+        ExecError::PubkeyType,
+        ExecError::SchnorrSigSize,
+        ExecError::SchnorrSigHashtype,
+        ExecError::SchnorrSig,
+        ExecError::TapscriptCheckMultiSig,
+        ExecError::PubkeyCount,
+        ExecError::SigCount,
+        ExecError::StackSize,
+        ExecError::CleanStack,
+        ExecError::WitnessProgramMismatch,
+        ExecError::WitnessPubkeyType,
+        ExecError::EvalFalse,
+        ExecError::ScriptIntNumericOverflow,
+        ExecError::Debug,
+        ExecError::OutOfGas,
+    ];
+
+    #[test]
+    fn codes_are_unique() {
+        let codes = ALL.iter().map(|e| e.code()).collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(codes.len(), ALL.len());
+    }
+
+    #[test]
+    fn categories() {
+        for err in ALL.iter() {
+            let expected = match err.code() {
+                100..=199 => ErrorCategory::Stack,
+                200..=299 => ErrorCategory::Numeric,
+                300..=399 => ErrorCategory::Signature,
+                400..=499 => ErrorCategory::Locktime,
+                500..=599 => ErrorCategory::Script,
+                600..=699 => ErrorCategory::Limit,
+                900..=999 => ErrorCategory::Other,
+                code => panic!("{:?} has code {} outside of any category", err, code),
+            };
+            assert_eq!(err.category(), expected, "{:?}", err);
+        }
+
+        // Codes are stable.
+        assert_eq!(ExecError::InvalidStackOperation.code(), 100);
+        assert_eq!(ExecError::SigNullDummy.code(), 313);
+        assert_eq!(ExecError::OutOfGas.code(), 604);
+        assert_eq!(ExecError::Debug.code(), 901);
+    }
+}
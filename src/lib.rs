@@ -2,15 +2,16 @@ extern crate alloc;
 extern crate core;
 
 use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 use core::cmp;
 
 use bitcoin::consensus::Encodable;
 use bitcoin::hashes::{hash160, ripemd160, sha1, sha256, sha256d, Hash};
-use bitcoin::opcodes::{all::*, Opcode};
+use bitcoin::opcodes::{all::*, Class, ClassifyContext, Opcode};
 use bitcoin::script::{self, Instruction, Instructions, Script, ScriptBuf};
-use bitcoin::sighash::SighashCache;
 use bitcoin::taproot::{self, TapLeafHash};
 use bitcoin::transaction::{self, Transaction, TxOut};
+use bitcoin::Witness;
 
 #[cfg(feature = "serde")]
 use serde;
@@ -22,9 +23,20 @@ mod utils;
 use utils::ConditionStack;
 
 mod signatures;
+pub use signatures::{
+    AlwaysInvalidChecker, AlwaysValidChecker, SignatureChecker, TxSignatureChecker,
+};
+
+pub mod timelock_utils;
+
+pub mod num_utils;
+
+pub mod parse_utils;
 
 mod error;
-pub use error::{Error, ExecError};
+pub use error::{
+    AsmBuilderError, Error, ErrorCategory, ExecError, MinimalPushError, ParseHexError,
+};
 
 #[cfg(feature = "json")]
 pub mod json;
@@ -32,7 +44,18 @@ pub mod json;
 mod wasm;
 
 mod data_structures;
-pub use data_structures::Stack;
+pub use data_structures::{Stack, StackDelta};
+
+mod analysis;
+pub use analysis::{check_conditional_balance, check_minimal_pushes, estimate, Estimate};
+
+mod builder;
+pub use builder::AsmBuilder;
+
+mod batch;
+#[cfg(feature = "rayon")]
+pub use batch::exec_batch;
+pub use batch::ExecJob;
 
 /// Maximum number of non-push operations per script
 const MAX_OPS_PER_SCRIPT: usize = 201;
@@ -57,17 +80,75 @@ const VALIDATION_WEIGHT_OFFSET: i64 = 50;
 const VALIDATION_WEIGHT_PER_SIGOP_PASSED: i64 = 50;
 
 // Maximum number of public keys per multisig
-const _MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
+const MAX_PUBKEYS_PER_MULTISIG: i64 = 20;
 
 /// Used to enable experimental script features.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Experimental {
     /// Enable an experimental implementation of OP_CAT.
     pub op_cat: bool,
+    /// Enable experimental implementations of OP_SUBSTR, OP_LEFT and OP_RIGHT.
+    pub op_splice: bool,
+    /// Enable experimental implementations of OP_AND, OP_OR and OP_XOR.
+    pub op_bitwise: bool,
+
+    /// Enable the experimental opcodes in legacy scripts, where they are
+    /// otherwise disabled.
+    pub legacy: bool,
+    /// Enable the experimental opcodes in segwit v0 scripts, where they are
+    /// otherwise disabled.
+    pub segwit_v0: bool,
+    /// Enable the experimental opcodes in Tapscript, where they are
+    /// otherwise OP_SUCCESSx.
+    pub tapscript: bool,
+}
+
+impl Experimental {
+    /// Whether the opcode is enabled as an experimental opcode in the context.
+    fn enables(&self, ctx: ExecCtx, op: Opcode) -> bool {
+        let enabled_in_ctx = match ctx {
+            ExecCtx::Legacy => self.legacy,
+            ExecCtx::SegwitV0 => self.segwit_v0,
+            ExecCtx::Tapscript => self.tapscript,
+        };
+        enabled_in_ctx
+            && match op {
+                OP_CAT => self.op_cat,
+                OP_SUBSTR | OP_LEFT | OP_RIGHT => self.op_splice,
+                OP_AND | OP_OR | OP_XOR => self.op_bitwise,
+                _ => false,
+            }
+    }
+}
+
+/// The maximum size of the numeric operands of arithmetic opcodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptNumWidth {
+    /// 4-byte operands, as required by consensus.
+    Consensus,
+    /// 8-byte operands, covering the full 64-bit range. This also enables
+    /// OP_MUL in all contexts.
+    ///
+    /// This is experimental and NOT consensus-compatible.
+    Wide64,
+}
+
+impl ScriptNumWidth {
+    fn size(self) -> usize {
+        match self {
+            ScriptNumWidth::Consensus => 4,
+            ScriptNumWidth::Wide64 => 8,
+        }
+    }
+
+    /// Whether the opcode is enabled by this width.
+    fn enables(self, op: Opcode) -> bool {
+        self == ScriptNumWidth::Wide64 && op == OP_MUL
+    }
 }
 
 /// Used to fine-tune different variables during execution.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Options {
     /// Require data pushes be minimally encoded.
     pub require_minimal: bool, //TODO(stevenroose) double check all fRequireMinimal usage in Core
@@ -75,23 +156,128 @@ pub struct Options {
     pub verify_cltv: bool,
     /// Verify OP_CHECKSEQUENCEVERIFY.
     pub verify_csv: bool,
-    /// Verify conditionals are minimally encoded.
+    /// Require exactly one item on the stack after legacy execution.
+    /// This is always required in segwit v0 and Tapscript.
+    pub require_clean_stack: bool,
+    /// Verify conditionals are minimally encoded in segwit v0.
+    /// This is always enforced in Tapscript.
     pub verify_minimal_if: bool,
-	/// Enfore a strict limit of 1000 total stack items.
-	pub enforce_stack_limit: bool,
+    /// Require ECDSA signatures to be strictly DER-encoded (BIP 66).
+    pub verify_dersig: bool,
+    /// Require the S value of ECDSA signatures to be at most half the curve order.
+    pub verify_low_s: bool,
+    /// Require ECDSA signatures to have a defined sighash type and public keys
+    /// to be either compressed or uncompressed.
+    pub verify_strictenc: bool,
+    /// Require the extra element popped by OP_CHECKMULTISIG to be empty (BIP 147).
+    pub verify_nulldummy: bool,
+    /// Maximum number of non-push opcodes per script, not counted in Tapscript.
+    pub max_ops: Option<usize>,
+    /// Maximum number of items on the stack and altstack combined.
+    pub max_stack_size: Option<usize>,
+    /// Enforce [Options::max_stack_size]. Setting this to false has the same
+    /// effect as setting [Options::max_stack_size] to [None].
+    #[deprecated(note = "set max_stack_size to None instead")]
+    pub enforce_stack_limit: bool,
+    /// Maximum size in bytes of a single stack item.
+    pub max_script_element_size: Option<usize>,
+    /// Keep track of the changes each step makes to the stack,
+    /// see [Exec::stack_delta].
+    pub track_stack_delta: bool,
+    /// Record a trace of all execution steps, see [Exec::trace].
+    pub trace: bool,
+    /// Fail execution when the spent gas exceeds this budget.
+    pub gas_budget: Option<u64>,
+    /// The gas cost of each opcode, see [default_opcode_weight].
+    pub opcode_weight: fn(Opcode) -> u64,
+    /// The maximum size of numeric operands of arithmetic opcodes, see [ScriptNumWidth].
+    pub arithmetic_width: ScriptNumWidth,
 
     pub experimental: Experimental,
 }
 
+// Implemented by hand because of the [Options::opcode_weight] function pointer.
+// It is compared by address, which is best-effort: the same function can have
+// different addresses in different codegen units and identical functions can
+// be merged into one.
+#[allow(deprecated)]
+impl PartialEq for Options {
+    fn eq(&self, other: &Options) -> bool {
+        let Options {
+            require_minimal,
+            verify_cltv,
+            verify_csv,
+            require_clean_stack,
+            verify_minimal_if,
+            verify_dersig,
+            verify_low_s,
+            verify_strictenc,
+            verify_nulldummy,
+            max_ops,
+            max_stack_size,
+            enforce_stack_limit,
+            max_script_element_size,
+            track_stack_delta,
+            trace,
+            gas_budget,
+            opcode_weight,
+            arithmetic_width,
+            experimental,
+        } = self;
+        *require_minimal == other.require_minimal
+            && *verify_cltv == other.verify_cltv
+            && *verify_csv == other.verify_csv
+            && *require_clean_stack == other.require_clean_stack
+            && *verify_minimal_if == other.verify_minimal_if
+            && *verify_dersig == other.verify_dersig
+            && *verify_low_s == other.verify_low_s
+            && *verify_strictenc == other.verify_strictenc
+            && *verify_nulldummy == other.verify_nulldummy
+            && *max_ops == other.max_ops
+            && *max_stack_size == other.max_stack_size
+            && *enforce_stack_limit == other.enforce_stack_limit
+            && *max_script_element_size == other.max_script_element_size
+            && *track_stack_delta == other.track_stack_delta
+            && *trace == other.trace
+            && *gas_budget == other.gas_budget
+            && core::ptr::fn_addr_eq(*opcode_weight, other.opcode_weight)
+            && *arithmetic_width == other.arithmetic_width
+            && *experimental == other.experimental
+    }
+}
+
+impl Eq for Options {}
+
 impl Default for Options {
+    #[allow(deprecated)]
     fn default() -> Self {
         Options {
             require_minimal: true,
             verify_cltv: true,
             verify_csv: true,
+            require_clean_stack: false,
             verify_minimal_if: true,
-			enforce_stack_limit: true,
-            experimental: Experimental { op_cat: true },
+            verify_dersig: true,
+            verify_low_s: false,
+            verify_strictenc: false,
+            verify_nulldummy: true,
+            max_ops: Some(MAX_OPS_PER_SCRIPT),
+            max_stack_size: Some(MAX_STACK_SIZE),
+            enforce_stack_limit: true,
+            max_script_element_size: Some(MAX_SCRIPT_ELEMENT_SIZE),
+            track_stack_delta: false,
+            trace: false,
+            gas_budget: None,
+            opcode_weight: default_opcode_weight,
+            arithmetic_width: ScriptNumWidth::Consensus,
+            experimental: Experimental {
+                op_cat: true,
+                op_splice: false,
+                op_bitwise: false,
+                legacy: false,
+                segwit_v0: false,
+                tapscript: true,
+            },
         }
     }
 }
@@ -103,6 +289,7 @@ pub enum ExecCtx {
     Tapscript,
 }
 
+#[derive(Debug, Clone)]
 pub struct TxTemplate {
     pub tx: Transaction,
     pub prevouts: Vec<TxOut>,
@@ -119,30 +306,34 @@ pub struct ExecutionResult {
 }
 
 impl ExecutionResult {
-    fn from_final_stack(ctx: ExecCtx, final_stack: Stack) -> ExecutionResult {
-        ExecutionResult {
-            success: match ctx {
-                ExecCtx::Legacy => {
-                    if final_stack.is_empty() {
-                        false
-                    } else if !script::read_scriptbool(&final_stack.last().unwrap()) {
-                        false
-                    } else {
-                        true
-                    }
+    fn from_final_stack(ctx: ExecCtx, opt: &Options, final_stack: Stack) -> ExecutionResult {
+        let top_true =
+            !final_stack.is_empty() && script::read_scriptbool(&final_stack.last().unwrap());
+        let error = match ctx {
+            ExecCtx::Legacy => {
+                if !top_true {
+                    Some(ExecError::EvalFalse)
+                } else if opt.require_clean_stack && final_stack.len() != 1 {
+                    Some(ExecError::CleanStack)
+                } else {
+                    None
                 }
-                ExecCtx::SegwitV0 | ExecCtx::Tapscript => {
-                    if final_stack.len() != 1 {
-                        false
-                    } else if !script::read_scriptbool(&final_stack.last().unwrap()) {
-                        false
-                    } else {
-                        true
-                    }
+            }
+            // Witness scripts always require a clean stack.
+            ExecCtx::SegwitV0 | ExecCtx::Tapscript => {
+                if final_stack.len() != 1 {
+                    Some(ExecError::CleanStack)
+                } else if !top_true {
+                    Some(ExecError::EvalFalse)
+                } else {
+                    None
                 }
-            },
+            }
+        };
+        ExecutionResult {
+            success: error.is_none(),
             final_stack: final_stack,
-            error: None,
+            error: error,
             opcode: None,
         }
     }
@@ -152,8 +343,11 @@ impl ExecutionResult {
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ExecStats {
     /// The highest number of stack items occurred during execution.
-    /// This counts both the stack and the altstack.
+    /// This counts both the stack and the altstack, like the consensus
+    /// stack size limit does. It is sampled after every step.
     pub max_nb_stack_items: usize,
+    /// The highest number of altstack items occurred during execution.
+    pub max_nb_altstack_items: usize,
 
     /// The number of opcodes executed, plus an additional one
     /// per signature in CHECKMULTISIG.
@@ -163,8 +357,52 @@ pub struct ExecStats {
     pub start_validation_weight: i64,
     /// The current remaining validation weight.
     pub validation_weight: i64,
+
+    /// The gas spent on executed opcodes, see [Options::opcode_weight].
+    pub gas_spent: u64,
+
+    /// The number of times each opcode was executed, keyed by the opcode byte.
+    /// Data pushes are counted under their push opcode, opcodes in
+    /// non-taken branches are not counted.
+    pub opcode_histogram: BTreeMap<u8, u64>,
 }
 
+/// A single step of the execution trace, see [Options::trace].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// The byte offset of the instruction in the script.
+    pub position: usize,
+    /// The opcode of the instruction, for data pushes this is the push opcode.
+    pub opcode: Opcode,
+    /// The number of items on the stack before the step.
+    pub stack_size_before: usize,
+    /// The number of items on the stack after the step.
+    pub stack_size_after: usize,
+    /// Whether the instruction was skipped because it is in a non-taken branch.
+    pub skipped: bool,
+}
+
+/// A snapshot of the execution state, see [Exec::snapshot].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecState {
+    result: Option<ExecutionResult>,
+    script_position: usize,
+    current_position: usize,
+    cond_stack: ConditionStack,
+    stack: Stack,
+    altstack: Stack,
+    last_codeseparator_pos: Option<u32>,
+    script_code_position: usize,
+    opcode_count: usize,
+    validation_weight: i64,
+    gas_spent: u64,
+    stats: ExecStats,
+}
+
+/// A callback invoked before each executed instruction with its opcode,
+/// its byte offset in the script and the current stack.
+pub type PreStepHook = Box<dyn FnMut(Opcode, usize, &Stack)>;
+
 /// Partial execution of a script.
 pub struct Exec {
     ctx: ExecCtx,
@@ -172,13 +410,16 @@ pub struct Exec {
     tx: TxTemplate,
     result: Option<ExecutionResult>,
 
-    sighashcache: SighashCache<Transaction>,
+    sig_checker: Box<dyn SignatureChecker>,
     script: &'static Script,
     instructions: Instructions<'static>,
     current_position: usize,
     cond_stack: ConditionStack,
     stack: Stack,
     altstack: Stack,
+    stack_delta: Option<StackDelta>,
+    trace: Vec<TraceEntry>,
+    pre_step_hook: Option<PreStepHook>,
     last_codeseparator_pos: Option<u32>,
     // Initially set to the whole script, but updated when
     // OP_CODESEPARATOR is encountered.
@@ -186,6 +427,7 @@ pub struct Exec {
 
     opcode_count: usize,
     validation_weight: i64,
+    gas_spent: u64,
 
     // runtime statistics
     stats: ExecStats,
@@ -208,6 +450,60 @@ impl Exec {
         tx: TxTemplate,
         script: ScriptBuf,
         script_witness: Vec<Vec<u8>>,
+    ) -> Result<Exec, Error> {
+        let sig_checker = Box::new(TxSignatureChecker::new(&tx));
+        Exec::with_signature_checker(ctx, opt, tx, script, script_witness, sig_checker)
+    }
+
+    /// Create a new [Exec] for a script given in hex, see [parse_hex_script].
+    pub fn from_hex(
+        ctx: ExecCtx,
+        opt: Options,
+        tx: TxTemplate,
+        script_hex: &str,
+        script_witness: Vec<Vec<u8>>,
+    ) -> Result<Exec, Error> {
+        let script = parse_hex_script(script_hex).map_err(Error::ParseHex)?;
+        Exec::new(ctx, opt, tx, script, script_witness)
+    }
+
+    /// Create a new [Exec] with the given items on the main stack and the altstack.
+    ///
+    /// Unlike the witness passed to [Exec::new], the items don't count towards
+    /// the Tapscript validation weight. They are still subject to
+    /// [Options::max_script_element_size].
+    pub fn with_initial_stack(
+        ctx: ExecCtx,
+        opt: Options,
+        tx: TxTemplate,
+        script: ScriptBuf,
+        stack: Vec<Vec<u8>>,
+        altstack: Vec<Vec<u8>>,
+    ) -> Result<Exec, Error> {
+        if let Some(max) = opt.max_script_element_size {
+            if stack.iter().chain(altstack.iter()).any(|item| item.len() > max) {
+                return Err(Error::Exec(ExecError::PushSize));
+            }
+        }
+
+        let mut ret = Exec::new(ctx, opt, tx, script, vec![])?;
+        ret.stack = Stack::from_u8_vec(stack);
+        ret.altstack = Stack::from_u8_vec(altstack);
+        if let Some(ref mut res) = ret.result {
+            res.final_stack = ret.stack.clone();
+        }
+        ret.update_stats();
+        Ok(ret)
+    }
+
+    /// Like [Exec::new], but signatures are verified by the given [SignatureChecker].
+    pub fn with_signature_checker(
+        ctx: ExecCtx,
+        opt: Options,
+        tx: TxTemplate,
+        script: ScriptBuf,
+        script_witness: Vec<Vec<u8>>,
+        sig_checker: Box<dyn SignatureChecker>,
     ) -> Result<Exec, Error> {
         if ctx == ExecCtx::Tapscript {
             if tx.taproot_annex_scriptleaf.is_none() {
@@ -222,14 +518,33 @@ impl Exec {
         }
 
         // We want to make sure the script is valid so we don't have to throw parsing errors
-        // while executing.
-        let instructions = if opt.require_minimal {
-            script.instructions_minimal()
-        } else {
-            script.instructions()
-        };
-        if let Some(err) = instructions.clone().find_map(|res| res.err()) {
-            return Err(Error::InvalidScript(err));
+        // while executing. In Tapscript, an OP_SUCCESSx makes the script succeed right away,
+        // so the script only has to be parsable up to the first one and the minimal push
+        // rules don't apply (BIP 342).
+        let mut success_op = None;
+        if ctx == ExecCtx::Tapscript {
+            for res in script.instructions() {
+                match res {
+                    Ok(Instruction::Op(op)) if is_success_op(op, &opt) => {
+                        success_op = Some(op);
+                        break;
+                    }
+                    Ok(_) => {}
+                    Err(err) => return Err(Error::InvalidScript(err)),
+                }
+            }
+        }
+        if success_op.is_none() {
+            let instructions = if opt.require_minimal {
+                script.instructions_minimal()
+            } else {
+                script.instructions()
+            };
+            for res in instructions {
+                if let Err(err) = res {
+                    return Err(Error::InvalidScript(err));
+                }
+            }
         }
 
         // *****
@@ -255,7 +570,7 @@ impl Exec {
             ctx: ctx,
             result: None,
 
-            sighashcache: SighashCache::new(tx.tx.clone()),
+            sig_checker: sig_checker,
             script: script,
             instructions: instructions,
             current_position: 0,
@@ -263,8 +578,12 @@ impl Exec {
             //TODO(stevenroose) does this need to be reversed?
             stack: Stack::from_u8_vec(script_witness),
             altstack: Stack::new(),
+            stack_delta: None,
+            trace: Vec::new(),
+            pre_step_hook: None,
             opcode_count: 0,
             validation_weight: start_validation_weight,
+            gas_spent: 0,
             last_codeseparator_pos: None,
             script_code: script,
 
@@ -277,6 +596,14 @@ impl Exec {
                 ..Default::default()
             },
         };
+        if let Some(op) = success_op {
+            ret.result = Some(ExecutionResult {
+                success: true,
+                error: None,
+                opcode: Some(op),
+                final_stack: ret.stack.clone(),
+            });
+        }
         ret.update_stats();
         Ok(ret)
     }
@@ -285,31 +612,150 @@ impl Exec {
     // SOME GETTERS //
     //////////////////
 
+    /// The result of the execution, if it has finished.
+    ///
+    /// Use [ExecutionResult::success] to tell whether it succeeded or failed.
     pub fn result(&self) -> Option<&ExecutionResult> {
         self.result.as_ref()
     }
 
+    /// Whether execution has finished, either successfully or not.
+    pub fn is_done(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// The byte offset in the script of the instruction that made execution
+    /// fail with an error, [None] if it didn't fail with an error (yet).
+    ///
+    /// Errors of the final stack checks at the end of the script, like
+    /// [ExecError::EvalFalse] and [ExecError::CleanStack], have no position.
+    pub fn last_error_position(&self) -> Option<usize> {
+        match self.result {
+            Some(ExecutionResult { error: Some(_), .. })
+                if self.current_position < self.script.len() =>
+            {
+                Some(self.current_position)
+            }
+            _ => None,
+        }
+    }
+
     pub fn script_position(&self) -> usize {
         self.script.len() - self.instructions.as_script().len()
     }
 
+    /// The part of the script that is yet to be executed, starting
+    /// with the next instruction.
     pub fn remaining_script<'a>(&'a self) -> &'a Script {
         let pos = self.script_position();
         &self.script[pos..]
     }
 
+    /// The opcode of the next instruction to be executed, for data pushes
+    /// this is the push opcode. [None] once execution is done.
+    pub fn current_opcode(&self) -> Option<Opcode> {
+        if self.result.is_some() {
+            return None;
+        }
+        self.remaining_script().as_bytes().first().map(|b| Opcode::from(*b))
+    }
+
+    /// The current main stack.
+    ///
+    /// Can be inspected between steps and after execution has finished.
     pub fn stack(&self) -> &Stack {
         &self.stack
     }
 
+    /// The current altstack.
+    ///
+    /// Can be inspected between steps and after execution has finished.
     pub fn altstack(&self) -> &Stack {
         &self.altstack
     }
 
+    /// The changes the last executed step made to the main stack.
+    ///
+    /// Only available when [Options::track_stack_delta] is set and the last
+    /// step succeeded. Items moved to or from the altstack are reported as
+    /// popped or pushed on the main stack.
+    pub fn stack_delta(&self) -> Option<&StackDelta> {
+        self.stack_delta.as_ref()
+    }
+
+    /// The trace of all successfully executed steps so far.
+    ///
+    /// Only recorded when [Options::trace] is set.
+    pub fn trace(&self) -> &[TraceEntry] {
+        &self.trace
+    }
+
     pub fn stats(&self) -> &ExecStats {
         &self.stats
     }
 
+    /// The opcode position of the last executed OP_CODESEPARATOR,
+    /// as used in the BIP 342 signature hash.
+    pub fn last_codeseparator_pos(&self) -> Option<u32> {
+        self.last_codeseparator_pos
+    }
+
+    /// Set a hook to be called right before each instruction is executed.
+    ///
+    /// The hook is called before any of the checks that can make the
+    /// instruction fail, like the push size, opcode count and disabled opcode
+    /// checks. It is not called for instructions in non-taken branches.
+    pub fn set_pre_step_hook(&mut self, hook: PreStepHook) {
+        self.pre_step_hook = Some(hook);
+    }
+
+    /// Take a snapshot of the current execution state.
+    ///
+    /// The snapshot can later be restored with [Exec::restore] to resume
+    /// execution from this point, f.e. to explore different branches.
+    pub fn snapshot(&self) -> ExecState {
+        ExecState {
+            result: self.result.clone(),
+            script_position: self.script_position(),
+            current_position: self.current_position,
+            cond_stack: self.cond_stack.clone(),
+            stack: self.stack.clone(),
+            altstack: self.altstack.clone(),
+            last_codeseparator_pos: self.last_codeseparator_pos,
+            script_code_position: self.script.len() - self.script_code.len(),
+            opcode_count: self.opcode_count,
+            validation_weight: self.validation_weight,
+            gas_spent: self.gas_spent,
+            stats: self.stats.clone(),
+        }
+    }
+
+    /// Restore a snapshot taken with [Exec::snapshot].
+    ///
+    /// The snapshot must have been taken from this same [Exec]. The trace
+    /// and the stack delta are not part of the snapshot.
+    pub fn restore(&mut self, state: ExecState) {
+        let remaining = &self.script[state.script_position..];
+        self.instructions = if self.opt.require_minimal {
+            remaining.instructions_minimal()
+        } else {
+            remaining.instructions()
+        };
+        self.script_code = &self.script[state.script_code_position..];
+
+        self.result = state.result;
+        self.current_position = state.current_position;
+        self.cond_stack = state.cond_stack;
+        self.stack = state.stack;
+        self.altstack = state.altstack;
+        self.stack_delta = None;
+        self.last_codeseparator_pos = state.last_codeseparator_pos;
+        self.opcode_count = state.opcode_count;
+        self.validation_weight = state.validation_weight;
+        self.gas_spent = state.gas_spent;
+        self.stats = state.stats;
+    }
+
     ///////////////
     // UTILITIES //
     ///////////////
@@ -322,6 +768,7 @@ impl Exec {
             final_stack: self.stack.clone(),
         };
         self.result = Some(res);
+        self.update_stats();
         Err(self.result.as_ref().unwrap())
     }
 
@@ -333,14 +780,47 @@ impl Exec {
             final_stack: self.stack.clone(),
         };
         self.result = Some(res);
+        self.update_stats();
         Err(self.result.as_ref().unwrap())
     }
 
+    fn check_element_size(&self, len: usize) -> Result<(), ExecError> {
+        match self.opt.max_script_element_size {
+            Some(max) if len > max => Err(ExecError::PushSize),
+            _ => Ok(()),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn check_stack_size(&self) -> Result<(), ExecError> {
+        if !self.opt.enforce_stack_limit {
+            return Ok(());
+        }
+        match self.opt.max_stack_size {
+            Some(max) if self.stack.len() + self.altstack.len() > max => Err(ExecError::StackSize),
+            _ => Ok(()),
+        }
+    }
+
+    fn call_pre_step_hook(&mut self, op: Opcode) {
+        if let Some(ref mut hook) = self.pre_step_hook {
+            hook(op, self.current_position, &self.stack);
+        }
+    }
+
+    fn spend_gas(&mut self, gas: u64) -> Result<(), ExecError> {
+        self.gas_spent = self.gas_spent.saturating_add(gas);
+        match self.opt.gas_budget {
+            Some(budget) if self.gas_spent > budget => Err(ExecError::OutOfGas),
+            _ => Ok(()),
+        }
+    }
+
     fn check_lock_time(&mut self, lock_time: i64) -> bool {
         use bitcoin::locktime::absolute::LockTime;
-        let lock_time = match lock_time.try_into() {
-            Ok(l) => LockTime::from_consensus(l),
-            Err(_) => return false,
+        let lock_time = match timelock_utils::from_num_absolute(lock_time) {
+            Some(l) => l,
+            None => return false,
         };
 
         match (lock_time, self.tx.tx.lock_time) {
@@ -368,51 +848,74 @@ impl Exec {
         }
 
         let input_sequence = self.tx.tx.input[self.tx.input_idx].sequence;
-        let input_lock_time = match input_sequence.to_relative_lock_time() {
-            Some(lt) => lt,
-            None => return false,
-        };
-
         let lock_time = match LockTime::from_num(sequence) {
             Some(lt) => lt,
             None => return false,
         };
 
-        match (lock_time, input_lock_time) {
-            (LockTime::Blocks(h1), LockTime::Blocks(h2)) if h1 > h2 => return false,
-            (LockTime::Time(t1), LockTime::Time(t2)) if t1 > t2 => return false,
-            (LockTime::Blocks(_), LockTime::Time(_)) => return false,
-            (LockTime::Time(_), LockTime::Blocks(_)) => return false,
-            _ => {}
+        timelock_utils::check_relative_locktime(lock_time, input_sequence)
+    }
+
+    /// The scriptcode to check pre-Tapscript signatures against.
+    ///
+    /// The signatures are dropped from it in pre-segwit scripts but not in
+    /// segwit scripts. Like Core's FindAndDelete, this only drops pushes of a
+    /// signature with the minimal push opcode for its length that start where
+    /// an instruction starts. Empty signatures drop all OP_0s.
+    fn pre_tap_script_code(&self, sigs: &[&[u8]]) -> Cow<'static, [u8]> {
+        let script_code: &'static Script = self.script_code;
+        let bytes = script_code.as_bytes();
+        let mut scriptcode = Cow::Borrowed(bytes);
+        if self.ctx != ExecCtx::Legacy {
+            return scriptcode;
         }
 
-        true
+        let pushes = sigs
+            .iter()
+            .map(|sig| {
+                let sig = <&script::PushBytes>::try_from(*sig).expect("stack items are small");
+                let mut push = ScriptBuf::new();
+                push.push_slice(sig);
+                push.into_bytes()
+            })
+            .collect::<Vec<_>>();
+        let mut instructions = script_code.instructions();
+        let mut start = 0;
+        let mut removed = 0;
+        // An instruction that fails to parse ends the search, like in Core.
+        while let Some(Ok(_)) = instructions.next() {
+            let end = bytes.len() - instructions.as_script().len();
+            if pushes.iter().any(|p| p[..] == bytes[start..end]) {
+                scriptcode.to_mut().drain(start - removed..end - removed);
+                removed += end - start;
+            }
+            start = end;
+        }
+        scriptcode
     }
 
     fn check_sig_pre_tap(&mut self, sig: &[u8], pk: &[u8]) -> Result<bool, ExecError> {
         //TODO(stevenroose) somehow sigops limit should be checked somewhere
 
-        // Drop the signature in pre-segwit scripts but not segwit scripts
-        let mut scriptcode = Cow::Borrowed(self.script_code.as_bytes());
-        if self.ctx == ExecCtx::Legacy {
-            let mut i = 0;
-            while i < scriptcode.len() - sig.len() {
-                if &scriptcode[i..i + sig.len()] == sig {
-                    scriptcode.to_mut().drain(i..i + sig.len());
-                } else {
-                    i += 1;
-                }
-            }
-        }
-
-        //TODO(stevenroose) the signature and pk encoding checks we use here
-        // might not be exactly identical to Core's
+        let scriptcode = self.pre_tap_script_code(&[sig]);
+        self.verify_sig_pre_tap(sig, pk, &scriptcode)
+    }
 
+    /// Check the encoding of a pre-Tapscript signature and public key and
+    /// verify the signature against the given scriptcode.
+    fn verify_sig_pre_tap(
+        &mut self,
+        sig: &[u8],
+        pk: &[u8],
+        scriptcode: &[u8],
+    ) -> Result<bool, ExecError> {
+        self.check_signature_encoding(sig)?;
+        self.check_pubkey_encoding(pk)?;
         if self.ctx == ExecCtx::SegwitV0 && pk.len() == 65 {
             return Err(ExecError::WitnessPubkeyType);
         }
 
-        Ok(self.check_sig_ecdsa(sig, pk, &scriptcode))
+        Ok(self.check_sig_ecdsa(sig, pk, scriptcode))
     }
 
     fn check_sig_tap(&mut self, sig: &[u8], pk: &[u8]) -> Result<bool, ExecError> {
@@ -423,6 +926,9 @@ impl Exec {
             }
         }
 
+        // An empty public key always fails the script. Otherwise, an empty
+        // signature never succeeds but doesn't fail the script either,
+        // whatever the type of the public key.
         if pk.is_empty() {
             Err(ExecError::PubkeyType)
         } else if pk.len() == 32 {
@@ -433,7 +939,8 @@ impl Exec {
                 Ok(false)
             }
         } else {
-            Ok(true)
+            // Unknown public key types are treated as a successful check.
+            Ok(!sig.is_empty())
         }
     }
 
@@ -448,7 +955,15 @@ impl Exec {
     // EXECUTION //
     ///////////////
 
-    /// Returns true when execution is done.
+    /// Execute a single instruction of the script.
+    ///
+    /// Returns [Ok] if execution can continue and [Err] with the final
+    /// [ExecutionResult] when it is done. The stack and altstack can be
+    /// inspected in between calls.
+    ///
+    /// When the script pointer is past the last instruction, the final stack
+    /// is evaluated and execution is reported as done. Once done, every
+    /// subsequent call returns the same result without executing anything.
     pub fn exec_next(&mut self) -> Result<(), &ExecutionResult> {
         if let Some(ref res) = self.result {
             return Err(res);
@@ -458,42 +973,77 @@ impl Exec {
         let instruction = match self.instructions.next() {
             Some(Ok(i)) => i,
             None => {
-                let res = ExecutionResult::from_final_stack(self.ctx, self.stack.clone());
+                let res =
+                    ExecutionResult::from_final_stack(self.ctx, &self.opt, self.stack.clone());
                 self.result = Some(res);
                 return Err(self.result.as_ref().unwrap());
             }
             Some(Err(_)) => unreachable!("we checked the script beforehand"),
         };
 
+        self.stack_delta = None;
+        let stack_before = if self.opt.track_stack_delta {
+            Some(self.stack.clone())
+        } else {
+            None
+        };
+        let stack_size_before = self.stack.len();
+
         let exec = self.cond_stack.all_true();
-        match instruction {
+        let executed = match instruction {
             Instruction::PushBytes(p) => {
-                if p.len() > MAX_SCRIPT_ELEMENT_SIZE {
-                    return self.fail(ExecError::PushSize);
+                let op = Opcode::from(self.script.as_bytes()[self.current_position]);
+                if exec {
+                    self.call_pre_step_hook(op);
+                }
+                if let Err(err) = self.check_element_size(p.len()) {
+                    return self.fail(err);
                 }
                 if exec {
+                    // Pushes cost their opcode plus one per byte of data.
+                    let gas = (self.opt.opcode_weight)(op) + p.len() as u64;
+                    if let Err(err) = self.spend_gas(gas) {
+                        return self.failop(err, op);
+                    }
+                    self.count_opcode(op);
                     self.stack.pushstr(p.as_bytes());
+                    if let Err(err) = self.check_stack_size() {
+                        return self.fail(err);
+                    }
                 }
+                exec
             }
             Instruction::Op(op) => {
+                // Conditionals update the condition stack even in non-taken
+                // branches, but they only count as executed if their own IF is.
+                let runs =
+                    exec || (op.to_u8() >= OP_IF.to_u8() && op.to_u8() <= OP_ENDIF.to_u8());
+                let executed = match op {
+                    OP_ELSE | OP_ENDIF => self.cond_stack.all_true_below_top(),
+                    _ => exec,
+                };
+                if executed {
+                    self.call_pre_step_hook(op);
+                }
+
                 // Some things we do even when we're not executing.
 
                 // Note how OP_RESERVED does not count towards the opcode limit.
                 if self.ctx == ExecCtx::Legacy || self.ctx == ExecCtx::SegwitV0 {
                     if op.to_u8() > OP_PUSHNUM_16.to_u8() {
                         self.opcode_count += 1;
-                        if self.opcode_count > MAX_OPS_PER_SCRIPT {
+                        if self.opt.max_ops.is_some_and(|max| self.opcode_count > max) {
                             return self.fail(ExecError::OpCount);
                         }
                     }
                 }
 
                 match op {
-                    OP_CAT if !self.opt.experimental.op_cat || self.ctx != ExecCtx::Tapscript => {
-                        return self.failop(ExecError::DisabledOpcode, op);
-                    }
-                    OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR | OP_XOR
-                    | OP_2MUL | OP_2DIV | OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT | OP_RSHIFT => {
+                    _ if self.opt.experimental.enables(self.ctx, op) => {}
+                    _ if self.opt.arithmetic_width.enables(op) => {}
+                    OP_CAT | OP_SUBSTR | OP_LEFT | OP_RIGHT | OP_INVERT | OP_AND | OP_OR
+                    | OP_XOR | OP_2MUL | OP_2DIV | OP_MUL | OP_DIV | OP_MOD | OP_LSHIFT
+                    | OP_RSHIFT => {
                         return self.failop(ExecError::DisabledOpcode, op);
                     }
                     OP_RESERVED => {
@@ -503,18 +1053,45 @@ impl Exec {
                     _ => {}
                 }
 
-                if exec || (op.to_u8() >= OP_IF.to_u8() && op.to_u8() <= OP_ENDIF.to_u8()) {
+                if executed {
+                    if let Err(err) = self.spend_gas((self.opt.opcode_weight)(op)) {
+                        return self.failop(err, op);
+                    }
+                    self.count_opcode(op);
+                }
+                if runs {
                     if let Err(err) = self.exec_opcode(op) {
                         return self.failop(err, op);
                     }
                 }
+                executed
             }
+        };
+
+        if self.opt.trace {
+            self.trace.push(TraceEntry {
+                position: self.current_position,
+                opcode: Opcode::from(self.script.as_bytes()[self.current_position]),
+                stack_size_before: stack_size_before,
+                stack_size_after: self.stack.len(),
+                skipped: !executed,
+            });
+        }
+
+        if let Some(before) = stack_before {
+            self.stack_delta = Some(self.stack.delta_from(&before));
         }
 
         self.update_stats();
         Ok(())
     }
 
+    /// Execute the remaining script until execution is done.
+    pub fn run(&mut self) -> &ExecutionResult {
+        while self.exec_next().is_ok() {}
+        self.result.as_ref().unwrap()
+    }
+
     fn exec_opcode(&mut self, op: Opcode) -> Result<(), ExecError> {
         let exec = self.cond_stack.all_true();
 
@@ -551,7 +1128,7 @@ impl Exec {
                 // Thus as a special case we tell CScriptNum to accept up
                 // to 5-byte bignums, which are good until 2**39-1, well
                 // beyond the 2**32-1 limit of the nLockTime field itself.
-                let n = read_scriptint(&top, 5, self.opt.require_minimal)?;
+                let n = read_scriptint_locktime(&top, self.opt.require_minimal)?;
 
                 if n < 0 {
                     return Err(ExecError::NegativeLocktime);
@@ -569,7 +1146,7 @@ impl Exec {
                 // nSequence, like nLockTime, is a 32-bit unsigned integer
                 // field. See the comment in CHECKLOCKTIMEVERIFY regarding
                 // 5-byte numeric operands.
-                let n = read_scriptint(&top, 5, self.opt.require_minimal)?;
+                let n = read_scriptint_locktime(&top, self.opt.require_minimal)?;
 
                 if n < 0 {
                     return Err(ExecError::NegativeLocktime);
@@ -604,7 +1181,7 @@ impl Exec {
                     // Under segwit v0 only enabled as policy.
                     if self.opt.verify_minimal_if && self.ctx == ExecCtx::SegwitV0 {
                         if top.len() > 1 || (top.len() == 1 && top[0] != 1) {
-                            return Err(ExecError::TapscriptMinimalIf);
+                            return Err(ExecError::MinimalIf);
                         }
                     }
                     let b = if op == OP_NOTIF {
@@ -806,18 +1383,51 @@ impl Exec {
                 self.stack.push(x2);
             }
 
-            OP_CAT if self.opt.experimental.op_cat && self.ctx == ExecCtx::Tapscript => {
+            // Experimental, only reached when enabled.
+            OP_CAT => {
                 // (x1 x2 -- x1|x2)
                 self.stack.needn(2)?;
                 let x2 = self.stack.popstr().unwrap();
                 let x1 = self.stack.popstr().unwrap();
                 let ret: Vec<u8> = x1.into_iter().chain(x2.into_iter()).collect();
-                if ret.len() > MAX_SCRIPT_ELEMENT_SIZE {
-                    return Err(ExecError::PushSize);
-                }
+                self.check_element_size(ret.len())?;
                 self.stack.pushstr(&ret);
             }
 
+            // Experimental, only reached when enabled.
+            OP_SUBSTR => {
+                // (in begin size -- out)
+                self.stack.needn(3)?;
+                let begin = self.stack.topnum(-2, self.opt.require_minimal)?;
+                let size = self.stack.topnum(-1, self.opt.require_minimal)?;
+                if begin < 0 || size < 0 {
+                    return Err(ExecError::InvalidStackOperation);
+                }
+                let x = self.stack.topstr(-3)?;
+                let begin = cmp::min(begin as usize, x.len());
+                let end = cmp::min(begin.saturating_add(size as usize), x.len());
+                self.stack.popn(3).unwrap();
+                self.stack.pushstr(&x[begin..end]);
+            }
+
+            // Experimental, only reached when enabled.
+            OP_LEFT | OP_RIGHT => {
+                // (in size -- out)
+                self.stack.needn(2)?;
+                let size = self.stack.topnum(-1, self.opt.require_minimal)?;
+                if size < 0 {
+                    return Err(ExecError::InvalidStackOperation);
+                }
+                let x = self.stack.topstr(-2)?;
+                let size = cmp::min(size as usize, x.len());
+                self.stack.popn(2).unwrap();
+                if op == OP_LEFT {
+                    self.stack.pushstr(&x[..size]);
+                } else {
+                    self.stack.pushstr(&x[x.len() - size..]);
+                }
+            }
+
             OP_SIZE => {
                 // (in -- in size)
                 let top = self.stack.topstr(-1)?;
@@ -826,6 +1436,30 @@ impl Exec {
 
             //
             // Bitwise logic
+
+            // Experimental, only reached when enabled.
+            OP_AND | OP_OR | OP_XOR => {
+                // (x1 x2 -- out)
+                // Both operands must have the same size.
+                let x1 = self.stack.topstr(-2)?;
+                let x2 = self.stack.topstr(-1)?;
+                if x1.len() != x2.len() {
+                    return Err(ExecError::InvalidStackOperation);
+                }
+                let ret: Vec<u8> = x1
+                    .iter()
+                    .zip(x2.iter())
+                    .map(|(a, b)| match op {
+                        OP_AND => a & b,
+                        OP_OR => a | b,
+                        OP_XOR => a ^ b,
+                        _ => unreachable!(),
+                    })
+                    .collect();
+                self.stack.popn(2).unwrap();
+                self.stack.pushstr(&ret);
+            }
+
             OP_EQUAL | OP_EQUALVERIFY => {
                 // (x1 x2 - bool)
                 self.stack.needn(2)?;
@@ -845,7 +1479,8 @@ impl Exec {
             // Numeric
             OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
                 // (in -- out)
-                let x = self.stack.topnum(-1, self.opt.require_minimal)?;
+                let size = self.opt.arithmetic_width.size();
+                let x = self.stack.topnum_size(-1, size, self.opt.require_minimal)?;
                 let res = match op {
                     OP_1ADD => x
                         .checked_add(1)
@@ -859,12 +1494,17 @@ impl Exec {
                     OP_0NOTEQUAL => (x != 0) as i64,
                     _ => unreachable!(),
                 };
+                // The only result that doesn't fit an 8-byte script number.
+                if res == i64::MIN {
+                    return Err(ExecError::ScriptIntNumericOverflow);
+                }
                 self.stack.pop().unwrap();
                 self.stack.pushnum(res);
             }
 
             OP_ADD
             | OP_SUB
+            | OP_MUL
             | OP_BOOLAND
             | OP_BOOLOR
             | OP_NUMEQUAL
@@ -877,8 +1517,9 @@ impl Exec {
             | OP_MIN
             | OP_MAX => {
                 // (x1 x2 -- out)
-                let x1 = self.stack.topnum(-2, self.opt.require_minimal)?;
-                let x2 = self.stack.topnum(-1, self.opt.require_minimal)?;
+                let size = self.opt.arithmetic_width.size();
+                let x1 = self.stack.topnum_size(-2, size, self.opt.require_minimal)?;
+                let x2 = self.stack.topnum_size(-1, size, self.opt.require_minimal)?;
                 let res = match op {
                     OP_ADD => x1
                         .checked_add(x2)
@@ -886,6 +1527,10 @@ impl Exec {
                     OP_SUB => x1
                         .checked_sub(x2)
                         .ok_or(ExecError::ScriptIntNumericOverflow)?,
+                    // Only reached with ScriptNumWidth::Wide64.
+                    OP_MUL => x1
+                        .checked_mul(x2)
+                        .ok_or(ExecError::ScriptIntNumericOverflow)?,
                     OP_BOOLAND => (x1 != 0 && x2 != 0) as i64,
                     OP_BOOLOR => (x1 != 0 || x2 != 0) as i64,
                     OP_NUMEQUAL => (x1 == x2) as i64,
@@ -899,6 +1544,10 @@ impl Exec {
                     OP_MAX => cmp::max(x1, x2),
                     _ => unreachable!(),
                 };
+                // The only result that doesn't fit an 8-byte script number.
+                if res == i64::MIN {
+                    return Err(ExecError::ScriptIntNumericOverflow);
+                }
                 if op == OP_NUMEQUALVERIFY && res == 0 {
                     return Err(ExecError::NumEqualVerify);
                 }
@@ -910,9 +1559,10 @@ impl Exec {
 
             OP_WITHIN => {
                 // (x min max -- out)
-                let x1 = self.stack.topnum(-3, self.opt.require_minimal)?;
-                let x2 = self.stack.topnum(-2, self.opt.require_minimal)?;
-                let x3 = self.stack.topnum(-1, self.opt.require_minimal)?;
+                let size = self.opt.arithmetic_width.size();
+                let x1 = self.stack.topnum_size(-3, size, self.opt.require_minimal)?;
+                let x2 = self.stack.topnum_size(-2, size, self.opt.require_minimal)?;
+                let x3 = self.stack.topnum_size(-1, size, self.opt.require_minimal)?;
                 self.stack.popn(3).unwrap();
                 let res = x2 <= x1 && x1 < x3;
                 let item = if res { 1 } else { 0 };
@@ -951,8 +1601,11 @@ impl Exec {
 
             OP_CODESEPARATOR => {
                 // Store this CODESEPARATOR position and update the scriptcode.
-                self.last_codeseparator_pos = Some(self.current_position as u32);
-                self.script_code = &self.script[self.current_position..];
+                // Note that BIP 342 uses the opcode position, not the byte offset.
+                let opcode_pos = self.script[..self.current_position].instructions().count();
+                self.last_codeseparator_pos = Some(opcode_pos as u32);
+                // The scriptcode starts right after the CODESEPARATOR.
+                self.script_code = &self.script[self.current_position + 1..];
             }
 
             OP_CHECKSIG | OP_CHECKSIGVERIFY => {
@@ -985,16 +1638,79 @@ impl Exec {
             }
 
             OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
-                unimplemented!();
+                if self.ctx == ExecCtx::Tapscript {
+                    return Err(ExecError::TapscriptCheckMultiSig);
+                }
+
+                // Stack layout from the top: number of keys, the keys, number
+                // of signatures, the signatures and the dummy element.
+                let mut i: isize = 1;
+                let nb_keys = self.stack.topnum(-i, self.opt.require_minimal)?;
+                if nb_keys < 0 || nb_keys > MAX_PUBKEYS_PER_MULTISIG {
+                    return Err(ExecError::PubkeyCount);
+                }
+                self.opcode_count += nb_keys as usize;
+                if self.opt.max_ops.is_some_and(|max| self.opcode_count > max) {
+                    return Err(ExecError::OpCount);
+                }
+                let first_key = i + 1;
+                i += 1 + nb_keys as isize;
+
+                let nb_sigs = self.stack.topnum(-i, self.opt.require_minimal)?;
+                if nb_sigs < 0 || nb_sigs > nb_keys {
+                    return Err(ExecError::SigCount);
+                }
+                let first_sig = i + 1;
+                i += 1 + nb_sigs as isize;
+                // Also require the dummy element.
+                self.stack.needn(i as usize)?;
+
+                let keys = (0..nb_keys as isize)
+                    .map(|k| self.stack.topstr(-first_key - k))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let sigs = (0..nb_sigs as isize)
+                    .map(|k| self.stack.topstr(-first_sig - k))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // All signatures are dropped from the scriptcode before checking any.
+                let sig_refs = sigs.iter().map(|s| &s[..]).collect::<Vec<_>>();
+                let scriptcode = self.pre_tap_script_code(&sig_refs);
+
+                // Signatures must be in the same order as their keys.
+                let mut res = true;
+                let (mut isig, mut ikey) = (0, 0);
+                while res && isig < sigs.len() {
+                    if self.verify_sig_pre_tap(&sigs[isig], &keys[ikey], &scriptcode)? {
+                        isig += 1;
+                    }
+                    ikey += 1;
+                    // Fail early if there are not enough keys left.
+                    if sigs.len() - isig > keys.len() - ikey {
+                        res = false;
+                    }
+                }
+
+                self.stack.popn(i as usize - 1).unwrap();
+                // A consensus bug makes OP_CHECKMULTISIG pop one extra element.
+                if self.opt.verify_nulldummy && !self.stack.topstr(-1)?.is_empty() {
+                    return Err(ExecError::SigNullDummy);
+                }
+                self.stack.popn(1).unwrap();
+
+                if op == OP_CHECKMULTISIGVERIFY && !res {
+                    return Err(ExecError::CheckMultiSigVerify);
+                }
+                if op == OP_CHECKMULTISIG {
+                    let ret = if res { 1 } else { 0 };
+                    self.stack.pushnum(ret);
+                }
             }
 
             // remainder
             _ => return Err(ExecError::BadOpcode),
         }
 
-        if self.opt.enforce_stack_limit && self.stack.len() + self.altstack.len() > MAX_STACK_SIZE {
-            return Err(ExecError::StackSize);
-        }
+        self.check_stack_size()?;
 
         Ok(())
     }
@@ -1003,13 +1719,130 @@ impl Exec {
     // STATISTICS //
     ////////////////
 
+    fn count_opcode(&mut self, op: Opcode) {
+        *self.stats.opcode_histogram.entry(op.to_u8()).or_insert(0) += 1;
+    }
+
     fn update_stats(&mut self) {
         let stack_items = self.stack.len() + self.altstack.len();
         self.stats.max_nb_stack_items = cmp::max(self.stats.max_nb_stack_items, stack_items);
+        self.stats.max_nb_altstack_items =
+            cmp::max(self.stats.max_nb_altstack_items, self.altstack.len());
 
         self.stats.opcode_count = self.opcode_count;
         self.stats.validation_weight = self.validation_weight;
+        self.stats.gas_spent = self.gas_spent;
+    }
+}
+
+/// Run the script to completion and return the statistics if it succeeded.
+fn run_to_stats(mut exec: Exec) -> Result<ExecStats, Error> {
+    let res = exec.run();
+    if res.success {
+        Ok(exec.stats().clone())
+    } else {
+        Err(Error::Exec(res.error.clone().unwrap_or(ExecError::EvalFalse)))
+    }
+}
+
+/// Execute a P2WSH input.
+///
+/// The input executed is the one at [TxTemplate::input_idx], so that it can't
+/// disagree with the input the BIP 143 signature hash commits to. Its prevout
+/// must commit to the given witness script. The [witness] holds the items for
+/// the initial stack, without the witness script itself.
+///
+/// The consensus rules of segwit v0 and the minimal push rule are always
+/// enforced, whatever [opt] says about them.
+#[allow(deprecated)]
+pub fn exec_p2wsh(
+    tx: TxTemplate,
+    witness: &Witness,
+    witness_script: &Script,
+    opt: Options,
+) -> Result<ExecStats, Error> {
+    let prevout = tx
+        .prevouts
+        .get(tx.input_idx)
+        .ok_or(Error::Other("missing prevout for input"))?;
+    if !prevout.script_pubkey.is_p2wsh() {
+        return Err(Error::Other("prevout is not P2WSH"));
+    }
+    let script_hash = sha256::Hash::hash(witness_script.as_bytes());
+    if prevout.script_pubkey.as_bytes()[2..] != script_hash.to_byte_array()[..] {
+        return Err(Error::Exec(ExecError::WitnessProgramMismatch));
+    }
+
+    let opt = Options {
+        require_minimal: true,
+        verify_cltv: true,
+        verify_csv: true,
+        verify_dersig: true,
+        verify_nulldummy: true,
+        max_ops: Some(MAX_OPS_PER_SCRIPT),
+        max_stack_size: Some(MAX_STACK_SIZE),
+        enforce_stack_limit: true,
+        max_script_element_size: Some(MAX_SCRIPT_ELEMENT_SIZE),
+        arithmetic_width: ScriptNumWidth::Consensus,
+        experimental: Experimental {
+            segwit_v0: false,
+            ..opt.experimental
+        },
+        ..opt
+    };
+    let stack = witness.iter().map(|item| item.to_vec()).collect::<Vec<_>>();
+    let script = witness_script.to_owned();
+    let exec = Exec::with_initial_stack(ExecCtx::SegwitV0, opt, tx, script, stack, vec![])?;
+    run_to_stats(exec)
+}
+
+/// Parse a script from hex.
+///
+/// Unlike [ScriptBuf::from_hex], this checks that every instruction of the
+/// script can be parsed and reports the byte offset of the first one that
+/// can't, f.e. a push that declares more bytes than remain in the script.
+pub fn parse_hex_script(hex: &str) -> Result<ScriptBuf, ParseHexError> {
+    let script = ScriptBuf::from_hex(hex).map_err(ParseHexError::InvalidHex)?;
+
+    let mut instructions = script.instructions();
+    loop {
+        let offset = script.len() - instructions.as_script().len();
+        match instructions.next() {
+            None => break,
+            Some(Ok(_)) => {}
+            Some(Err(script::Error::EarlyEndOfScript)) => {
+                return Err(ParseHexError::TruncatedPush { offset: offset });
+            }
+            Some(Err(e)) => {
+                return Err(ParseHexError::InvalidScript { offset: offset, error: e });
+            }
+        }
     }
+
+    Ok(script)
+}
+
+/// The default gas cost of an opcode.
+///
+/// Signature checks are the most expensive, followed by hash operations.
+/// All other opcodes cost 1.
+pub fn default_opcode_weight(op: Opcode) -> u64 {
+    match op {
+        OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => 1000,
+        OP_CHECKSIG | OP_CHECKSIGVERIFY | OP_CHECKSIGADD => 50,
+        OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => 10,
+        _ => 1,
+    }
+}
+
+/// Whether the opcode is an OP_SUCCESSx in Tapscript.
+///
+/// Experimental opcodes that are enabled are not considered OP_SUCCESSx.
+fn is_success_op(op: Opcode, opt: &Options) -> bool {
+    if opt.experimental.enables(ExecCtx::Tapscript, op) || opt.arithmetic_width.enables(op) {
+        return false;
+    }
+    op.classify(ClassifyContext::TapScript) == Class::SuccessOp
 }
 
 fn read_scriptint(item: &[u8], size: usize, minimal: bool) -> Result<i64, ExecError> {
@@ -1019,3 +1852,971 @@ fn read_scriptint(item: &[u8], size: usize, minimal: bool) -> Result<i64, ExecEr
         script::ScriptIntError::NumericOverflow => ExecError::ScriptIntNumericOverflow,
     })
 }
+
+/// Read a locktime operand of OP_CHECKLOCKTIMEVERIFY or OP_CHECKSEQUENCEVERIFY.
+///
+/// Unlike other numeric operands, these are allowed to be up to 5 bytes long.
+pub fn read_scriptint_locktime(item: &[u8], minimal: bool) -> Result<i64, ExecError> {
+    read_scriptint(item, 5, minimal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::cell::RefCell;
+    use std::rc::Rc;
+
+    use bitcoin::absolute::LockTime;
+    use bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+    use bitcoin::sighash::{EcdsaSighashType, SighashCache, TapSighashType};
+    use bitcoin::{Amount, Sequence, TxIn};
+
+    pub(crate) fn tx_template() -> TxTemplate {
+        TxTemplate {
+            tx: Transaction {
+                version: transaction::Version::TWO,
+                lock_time: LockTime::ZERO,
+                input: vec![TxIn {
+                    sequence: Sequence::ZERO,
+                    ..Default::default()
+                }],
+                output: vec![],
+            },
+            prevouts: vec![TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::new(),
+            }],
+            input_idx: 0,
+            taproot_annex_scriptleaf: Some((TapLeafHash::all_zeros(), None)),
+        }
+    }
+
+    pub(crate) fn push_bytes(data: &[u8]) -> &script::PushBytes {
+        <&script::PushBytes>::try_from(data).unwrap()
+    }
+
+    pub(crate) fn ops(ops: &[Opcode]) -> ScriptBuf {
+        ops.iter().fold(script::Builder::new(), |b, op| b.push_opcode(*op)).into_script()
+    }
+
+    /// An [Exec] that considers all signatures valid.
+    pub(crate) fn new_exec(
+        ctx: ExecCtx,
+        opt: Options,
+        script: ScriptBuf,
+        witness: Vec<Vec<u8>>,
+    ) -> Exec {
+        let checker = Box::new(AlwaysValidChecker);
+        Exec::with_signature_checker(ctx, opt, tx_template(), script, witness, checker).unwrap()
+    }
+
+    pub(crate) fn exec_script(
+        ctx: ExecCtx,
+        opt: Options,
+        script: ScriptBuf,
+        witness: Vec<Vec<u8>>,
+    ) -> ExecutionResult {
+        new_exec(ctx, opt, script, witness).run().clone()
+    }
+
+    pub(crate) fn stack_items(stack: &Stack) -> Vec<Vec<u8>> {
+        stack.iter_str().collect()
+    }
+
+    #[test]
+    fn locktime_operand_size() {
+        let five = script::scriptint_vec(u32::MAX as i64);
+        assert_eq!(five.len(), 5);
+        assert_eq!(read_scriptint_locktime(&five, true), Ok(u32::MAX as i64));
+        assert_eq!(read_scriptint(&five, 4, true), Err(ExecError::ScriptIntNumericOverflow));
+
+        let six = script::scriptint_vec(1 << 40);
+        assert_eq!(six.len(), 6);
+        assert_eq!(read_scriptint_locktime(&six, true), Err(ExecError::ScriptIntNumericOverflow));
+    }
+
+    #[test]
+    fn cltv_accepts_5_byte_operand() {
+        let run = |n: i64| {
+            let script = script::Builder::new()
+                .push_slice(push_bytes(&script::scriptint_vec(n)))
+                .push_opcode(OP_CLTV)
+                .into_script();
+            let mut tx = tx_template();
+            tx.tx.lock_time = LockTime::from_time(u32::MAX).unwrap();
+            let checker = Box::new(AlwaysValidChecker);
+            let mut exec = Exec::with_signature_checker(
+                ExecCtx::Legacy,
+                Options::default(),
+                tx,
+                script,
+                vec![],
+                checker,
+            )
+            .unwrap();
+            exec.run().clone()
+        };
+
+        assert!(run(u32::MAX as i64).success);
+        assert_eq!(run(1 << 40).error, Some(ExecError::ScriptIntNumericOverflow));
+    }
+
+    #[test]
+    fn step_by_step() {
+        let script = ops(&[OP_PUSHNUM_1, OP_PUSHNUM_1, OP_ADD]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+
+        assert!(exec.exec_next().is_ok());
+        assert_eq!(stack_items(exec.stack()), vec![vec![1]]);
+        assert!(exec.exec_next().is_ok());
+        assert_eq!(stack_items(exec.stack()), vec![vec![1], vec![1]]);
+        assert!(exec.exec_next().is_ok());
+        assert_eq!(stack_items(exec.stack()), vec![vec![2]]);
+        assert!(!exec.is_done());
+
+        assert!(exec.exec_next().unwrap_err().success);
+        assert!(exec.is_done());
+        // Once done, the same result is returned again.
+        assert!(exec.exec_next().unwrap_err().success);
+    }
+
+    #[test]
+    fn stack_delta() {
+        let script = ops(&[OP_PUSHNUM_1, OP_PUSHNUM_2, OP_SWAP, OP_DUP, OP_DROP, OP_TOALTSTACK]);
+        let opt = Options {
+            track_stack_delta: true,
+            ..Default::default()
+        };
+        let mut exec = new_exec(ExecCtx::Legacy, opt, script, vec![]);
+        let step = |exec: &mut Exec| {
+            exec.exec_next().unwrap();
+            exec.stack_delta().cloned().unwrap()
+        };
+
+        assert_eq!(step(&mut exec), StackDelta { popped: 0, pushed: vec![vec![1]] });
+        assert_eq!(step(&mut exec), StackDelta { popped: 0, pushed: vec![vec![2]] });
+        assert_eq!(step(&mut exec), StackDelta { popped: 2, pushed: vec![vec![2], vec![1]] });
+        assert_eq!(step(&mut exec), StackDelta { popped: 0, pushed: vec![vec![1]] });
+        assert_eq!(step(&mut exec), StackDelta { popped: 1, pushed: vec![] });
+        assert_eq!(step(&mut exec), StackDelta { popped: 1, pushed: vec![] });
+    }
+
+    #[test]
+    fn no_stack_delta_by_default() {
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), ops(&[OP_PUSHNUM_1]), vec![]);
+        exec.exec_next().unwrap();
+        assert_eq!(exec.stack_delta(), None);
+    }
+
+    #[test]
+    fn configurable_element_size() {
+        let script = script::Builder::new().push_slice(push_bytes(&[1; 521])).into_script();
+        let res = exec_script(ExecCtx::Legacy, Options::default(), script.clone(), vec![]);
+        assert_eq!(res.error, Some(ExecError::PushSize));
+
+        let opt = Options {
+            max_script_element_size: None,
+            ..Default::default()
+        };
+        assert!(exec_script(ExecCtx::Legacy, opt, script, vec![]).success);
+    }
+
+    #[test]
+    fn configurable_op_count() {
+        let nops = |n: usize| {
+            let mut script = vec![OP_NOP; n];
+            script.push(OP_PUSHNUM_1);
+            ops(&script)
+        };
+
+        assert!(exec_script(ExecCtx::Legacy, Options::default(), nops(201), vec![]).success);
+        let res = exec_script(ExecCtx::Legacy, Options::default(), nops(202), vec![]);
+        assert_eq!(res.error, Some(ExecError::OpCount));
+
+        let opt = Options {
+            max_ops: None,
+            ..Default::default()
+        };
+        assert!(exec_script(ExecCtx::Legacy, opt, nops(202), vec![]).success);
+    }
+
+    #[test]
+    fn configurable_stack_size() {
+        let script = ops(&[OP_PUSHNUM_1; 1001]);
+        let res = exec_script(ExecCtx::Legacy, Options::default(), script.clone(), vec![]);
+        assert_eq!(res.error, Some(ExecError::StackSize));
+
+        let opt = Options {
+            max_stack_size: None,
+            ..Default::default()
+        };
+        assert!(exec_script(ExecCtx::Legacy, opt, script.clone(), vec![]).success);
+
+        #[allow(deprecated)]
+        let opt = Options {
+            enforce_stack_limit: false,
+            ..Default::default()
+        };
+        assert!(exec_script(ExecCtx::Legacy, opt, script, vec![]).success);
+    }
+
+    #[test]
+    fn checksigadd_2_of_3() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[1; 32]))
+            .push_opcode(OP_CHECKSIG)
+            .push_slice(push_bytes(&[2; 32]))
+            .push_opcode(OP_CHECKSIGADD)
+            .push_slice(push_bytes(&[3; 32]))
+            .push_opcode(OP_CHECKSIGADD)
+            .into_script();
+        let witness = vec![vec![3; 64], vec![], vec![1; 64]];
+        let res = exec_script(ExecCtx::Tapscript, Options::default(), script, witness);
+        assert!(res.success);
+        assert_eq!(stack_items(&res.final_stack), vec![vec![2]]);
+    }
+
+    #[test]
+    fn tapscript_unknown_pubkey_type() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[2; 33]))
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_NOT)
+            .into_script();
+        let res = exec_script(ExecCtx::Tapscript, Options::default(), script.clone(), vec![vec![]]);
+        assert!(res.success);
+        let witness = vec![vec![1; 64]];
+        let res = exec_script(ExecCtx::Tapscript, Options::default(), script, witness);
+        assert_eq!(res.error, Some(ExecError::EvalFalse));
+    }
+
+    #[test]
+    fn trace() {
+        let if_else = [OP_PUSHNUM_1, OP_IF, OP_PUSHNUM_2, OP_ELSE, OP_PUSHNUM_3, OP_ENDIF];
+        let opt = Options {
+            trace: true,
+            ..Default::default()
+        };
+        let mut exec = new_exec(ExecCtx::Legacy, opt, ops(&if_else), vec![]);
+        assert!(exec.run().success);
+
+        let steps = exec.trace().iter().map(|e| (e.opcode, e.skipped)).collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            vec![
+                (OP_PUSHNUM_1, false),
+                (OP_IF, false),
+                (OP_PUSHNUM_2, false),
+                (OP_ELSE, false),
+                (OP_PUSHNUM_3, true),
+                (OP_ENDIF, false),
+            ]
+        );
+        assert_eq!(exec.trace()[2].position, 2);
+        assert_eq!(exec.trace()[2].stack_size_before, 0);
+        assert_eq!(exec.trace()[2].stack_size_after, 1);
+
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), ops(&if_else), vec![]);
+        exec.run();
+        assert!(exec.trace().is_empty());
+    }
+
+    #[test]
+    fn trace_nested_skipped() {
+        let nested = [OP_PUSHNUM_1, OP_IF, OP_ELSE, OP_ENDIF];
+        let script = ops(&[&[OP_PUSHBYTES_0, OP_IF][..], &nested[..], &[OP_ENDIF][..]].concat());
+        let opt = Options {
+            trace: true,
+            ..Default::default()
+        };
+        let mut exec = new_exec(ExecCtx::Legacy, opt, script, vec![vec![1]]);
+        assert!(exec.run().success);
+
+        let steps = exec.trace().iter().map(|e| (e.opcode, e.skipped)).collect::<Vec<_>>();
+        assert_eq!(
+            steps,
+            vec![
+                (OP_PUSHBYTES_0, false),
+                (OP_IF, false),
+                (OP_PUSHNUM_1, true),
+                (OP_IF, true),
+                (OP_ELSE, true),
+                (OP_ENDIF, true),
+                (OP_ENDIF, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn altstack() {
+        let script = ops(&[OP_PUSHNUM_1, OP_TOALTSTACK]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        exec.exec_next().unwrap();
+        exec.exec_next().unwrap();
+        assert!(exec.stack().is_empty());
+        assert_eq!(stack_items(exec.altstack()), vec![vec![1]]);
+    }
+
+    #[test]
+    fn options_eq() {
+        assert_eq!(Options::default(), Options::default());
+        let opt = Options {
+            opcode_weight: |_| 1,
+            ..Default::default()
+        };
+        assert_ne!(opt, Options::default());
+    }
+
+    #[test]
+    fn gas() {
+        let opt = Options {
+            gas_budget: Some(10),
+            ..Default::default()
+        };
+        let mut exec =
+            new_exec(ExecCtx::Legacy, opt, ops(&[OP_PUSHNUM_1, OP_DUP, OP_DROP]), vec![]);
+        assert!(exec.run().success);
+        assert_eq!(exec.stats().gas_spent, 3);
+
+        let mut script = vec![OP_PUSHNUM_1];
+        script.extend([OP_HASH256; 10]);
+        let opt = Options {
+            gas_budget: Some(50),
+            ..Default::default()
+        };
+        let mut exec = new_exec(ExecCtx::Legacy, opt, ops(&script), vec![]);
+        let res = exec.run().clone();
+        assert_eq!(res.error, Some(ExecError::OutOfGas));
+        assert_eq!(res.opcode, Some(OP_HASH256));
+        assert_eq!(exec.stats().gas_spent, 51);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let script = ops(&[OP_PUSHNUM_1, OP_IF, OP_PUSHNUM_2, OP_ELSE, OP_PUSHNUM_3, OP_ENDIF]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        exec.exec_next().unwrap();
+        exec.exec_next().unwrap();
+        let snapshot = exec.snapshot();
+
+        assert!(exec.run().success);
+        assert_eq!(stack_items(exec.stack()), vec![vec![2]]);
+
+        exec.restore(snapshot.clone());
+        assert_eq!(exec.snapshot(), snapshot);
+        assert!(!exec.is_done());
+        assert!(exec.stack().is_empty());
+        assert_eq!(exec.current_opcode(), Some(OP_PUSHNUM_2));
+
+        assert!(exec.run().success);
+        assert_eq!(stack_items(exec.stack()), vec![vec![2]]);
+    }
+
+    #[test]
+    fn error_position() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[1, 2, 3]))
+            .push_opcode(OP_NOT)
+            .push_opcode(OP_VERIFY)
+            .into_script();
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert_eq!(exec.run().error, Some(ExecError::Verify));
+        assert_eq!(exec.last_error_position(), Some(5));
+
+        let script = ops(&[OP_PUSHNUM_1, OP_DROP, OP_DROP]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert_eq!(exec.last_error_position(), None);
+        assert_eq!(exec.run().error, Some(ExecError::InvalidStackOperation));
+        assert_eq!(exec.last_error_position(), Some(2));
+    }
+
+    #[test]
+    fn op_success() {
+        let construct = |ctx: ExecCtx, bytes: &[u8]| {
+            let script = ScriptBuf::from_bytes(bytes.to_vec());
+            let opt = Options::default();
+            let checker = Box::new(AlwaysValidChecker);
+            Exec::with_signature_checker(ctx, opt, tx_template(), script, vec![], checker)
+        };
+
+        // OP_RESERVED is OP_SUCCESS80 in Tapscript.
+        let mut exec = construct(ExecCtx::Tapscript, &[0x50]).unwrap();
+        assert!(exec.is_done());
+        let res = exec.run();
+        assert!(res.success);
+        assert_eq!(res.opcode, Some(OP_RESERVED));
+        let res = construct(ExecCtx::Legacy, &[0x50]).unwrap().run().clone();
+        assert_eq!(res.error, Some(ExecError::Debug));
+
+        // A non-minimal push before the OP_SUCCESSx doesn't matter...
+        let non_minimal = [OP_PUSHBYTES_1.to_u8(), 0x01];
+        assert!(construct(ExecCtx::Tapscript, &non_minimal).is_err());
+        let bytes = [&non_minimal[..], &[0x50][..]].concat();
+        assert!(construct(ExecCtx::Tapscript, &bytes).unwrap().run().success);
+
+        // ...and neither does anything after it.
+        let truncated = [0x50, OP_PUSHBYTES_10.to_u8()];
+        assert!(construct(ExecCtx::Tapscript, &truncated).unwrap().run().success);
+        assert!(construct(ExecCtx::Legacy, &truncated).is_err());
+    }
+
+    #[test]
+    fn opcode_histogram() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[2; 33]))
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_PUSHBYTES_0)
+            .push_opcode(OP_IF)
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert!(exec.run().success);
+
+        let histogram = &exec.stats().opcode_histogram;
+        assert_eq!(histogram.get(&OP_PUSHBYTES_33.to_u8()), Some(&1));
+        assert_eq!(histogram.get(&OP_DUP.to_u8()), Some(&2));
+        assert_eq!(histogram.get(&OP_HASH160.to_u8()), Some(&2));
+        assert_eq!(histogram.get(&OP_DROP.to_u8()), Some(&2));
+        assert_eq!(histogram.get(&OP_IF.to_u8()), Some(&1));
+        assert_eq!(histogram.get(&OP_ENDIF.to_u8()), Some(&1));
+    }
+
+    #[test]
+    fn opcode_histogram_nested_skipped() {
+        let nested = [OP_PUSHNUM_1, OP_IF, OP_ELSE, OP_ENDIF];
+        let script = ops(&[&[OP_PUSHBYTES_0, OP_IF][..], &nested[..], &[OP_ENDIF][..]].concat());
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![vec![1]]);
+        assert!(exec.run().success);
+
+        let histogram = &exec.stats().opcode_histogram;
+        assert_eq!(histogram.get(&OP_IF.to_u8()), Some(&1));
+        assert_eq!(histogram.get(&OP_ELSE.to_u8()), None);
+        assert_eq!(histogram.get(&OP_ENDIF.to_u8()), Some(&1));
+        assert_eq!(histogram.get(&OP_PUSHNUM_1.to_u8()), None);
+    }
+
+    #[test]
+    fn initial_stack() {
+        let opt = Options::default();
+        let script = ops(&[OP_ADD, OP_FROMALTSTACK, OP_ADD]);
+        let stack = vec![vec![2], vec![3]];
+        let altstack = vec![vec![4]];
+        let mut exec =
+            Exec::with_initial_stack(ExecCtx::Legacy, opt, tx_template(), script, stack, altstack)
+                .unwrap();
+        assert_eq!(stack_items(exec.altstack()), vec![vec![4]]);
+        assert!(exec.run().success);
+        assert_eq!(stack_items(exec.stack()), vec![vec![9]]);
+        assert!(exec.altstack().is_empty());
+
+        let res = Exec::with_initial_stack(
+            ExecCtx::Legacy,
+            Options::default(),
+            tx_template(),
+            ops(&[OP_PUSHNUM_1]),
+            vec![],
+            vec![vec![0; 521]],
+        );
+        assert_eq!(res.err(), Some(Error::Exec(ExecError::PushSize)));
+    }
+
+    /// Records the scriptcode or the codeseparator position of every signature check.
+    #[derive(Clone, Default)]
+    struct RecordingChecker(Rc<RefCell<Vec<(Vec<u8>, u32)>>>);
+
+    impl SignatureChecker for RecordingChecker {
+        fn check_ecdsa(&mut self, _: ExecCtx, _: &[u8], _: &[u8], code: &Script, _: u32) -> bool {
+            self.0.borrow_mut().push((code.to_bytes(), u32::MAX));
+            true
+        }
+
+        fn check_schnorr(
+            &mut self,
+            _: &[u8],
+            _: &[u8],
+            _: TapSighashType,
+            _: TapLeafHash,
+            _: Option<&[u8]>,
+            codeseparator_pos: u32,
+        ) -> bool {
+            self.0.borrow_mut().push((vec![], codeseparator_pos));
+            true
+        }
+    }
+
+    fn record_checksig(ctx: ExecCtx, pk: &[u8], sig: Vec<u8>, codesep: bool) -> (Vec<u8>, u32) {
+        let mut builder = script::Builder::new().push_slice(push_bytes(pk));
+        if codesep {
+            builder = builder.push_opcode(OP_CODESEPARATOR);
+        }
+        let script = builder.push_opcode(OP_CHECKSIG).into_script();
+
+        let checker = RecordingChecker::default();
+        let opt = Options::default();
+        let tx = tx_template();
+        let boxed = Box::new(checker.clone());
+        let mut exec =
+            Exec::with_signature_checker(ctx, opt, tx, script, vec![sig], boxed).unwrap();
+        assert!(exec.run().success);
+        if codesep && ctx == ExecCtx::Tapscript {
+            assert_eq!(exec.last_codeseparator_pos(), Some(1));
+        }
+        let recorded = checker.0.borrow().clone();
+        assert_eq!(recorded.len(), 1);
+        recorded[0].clone()
+    }
+
+    #[test]
+    fn codeseparator_legacy() {
+        // r = 1, s = 1, SIGHASH_ALL
+        let sig = vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x01];
+        let (with, _) = record_checksig(ExecCtx::Legacy, &[2; 33], sig.clone(), true);
+        let (without, _) = record_checksig(ExecCtx::Legacy, &[2; 33], sig, false);
+        assert_eq!(with, vec![OP_CHECKSIG.to_u8()]);
+        assert_eq!(without[without.len() - 1..], with[..]);
+        assert_ne!(with, without);
+
+        let mut cache = SighashCache::new(tx_template().tx);
+        let with = cache.legacy_signature_hash(0, Script::from_bytes(&with), 1).unwrap();
+        let without = cache.legacy_signature_hash(0, Script::from_bytes(&without), 1).unwrap();
+        assert_ne!(with, without);
+    }
+
+    /// The scriptcode the signature is checked against in a legacy script.
+    fn legacy_scriptcode(script: ScriptBuf, sig: Vec<u8>) -> Vec<u8> {
+        let checker = RecordingChecker::default();
+        let boxed = Box::new(checker.clone());
+        let mut exec = Exec::with_signature_checker(
+            ExecCtx::Legacy,
+            Options::default(),
+            tx_template(),
+            script,
+            vec![sig],
+            boxed,
+        )
+        .unwrap();
+        assert!(exec.run().success);
+        let recorded = checker.0.borrow().clone();
+        assert_eq!(recorded.len(), 1);
+        recorded[0].0.clone()
+    }
+
+    #[test]
+    fn find_and_delete() {
+        // r = 1, s = 1, SIGHASH_ALL
+        let sig = vec![0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01, 0x01];
+        let push = |data: &[u8]| {
+            script::Builder::new().push_slice(push_bytes(data)).into_script().into_bytes()
+        };
+        let checksig = [&push(&[2; 33])[..], &[OP_CHECKSIG.to_u8()][..]].concat();
+        let drop = [OP_DROP.to_u8()];
+        let drop2 = [OP_2DROP.to_u8()];
+        let run = |parts: &[&[u8]]| {
+            legacy_scriptcode(ScriptBuf::from_bytes(parts.concat()), sig.clone())
+        };
+
+        // Pushes of the signature are dropped.
+        let code = run(&[&push(&sig)[..], &drop[..], &checksig[..]]);
+        assert_eq!(code, [&drop[..], &checksig[..]].concat());
+        let code = run(&[&push(&sig)[..], &push(&sig)[..], &drop2[..], &checksig[..]]);
+        assert_eq!(code, [&drop2[..], &checksig[..]].concat());
+
+        // But not inside other pushes, whether push-encoded or not.
+        let inner = push(&[&[0xaa][..], &push(&sig)[..]].concat());
+        let code = run(&[&inner[..], &drop[..], &checksig[..]]);
+        assert_eq!(code, [&inner[..], &drop[..], &checksig[..]].concat());
+        let inner = push(&[&sig[..], &[0xbb][..]].concat());
+        let code = run(&[&inner[..], &drop[..], &checksig[..]]);
+        assert_eq!(code, [&inner[..], &drop[..], &checksig[..]].concat());
+    }
+
+    #[test]
+    fn codeseparator_tapscript() {
+        let (_, pos) = record_checksig(ExecCtx::Tapscript, &[1; 32], vec![1; 64], true);
+        assert_eq!(pos, 1);
+        let (_, pos) = record_checksig(ExecCtx::Tapscript, &[1; 32], vec![1; 64], false);
+        assert_eq!(pos, u32::MAX);
+    }
+
+    #[test]
+    fn minimal_if() {
+        let script = ops(&[OP_IF, OP_PUSHNUM_1, OP_ELSE, OP_PUSHNUM_1, OP_ENDIF]);
+        let run = |ctx: ExecCtx, verify_minimal_if: bool, witness: Vec<Vec<u8>>| {
+            let opt = Options {
+                verify_minimal_if: verify_minimal_if,
+                ..Default::default()
+            };
+            exec_script(ctx, opt, script.clone(), witness).error
+        };
+
+        assert_eq!(run(ExecCtx::SegwitV0, true, vec![vec![1]]), None);
+        assert_eq!(run(ExecCtx::SegwitV0, true, vec![vec![]]), None);
+        assert_eq!(run(ExecCtx::SegwitV0, true, vec![vec![2]]), Some(ExecError::MinimalIf));
+        assert_eq!(run(ExecCtx::SegwitV0, true, vec![vec![0, 1]]), Some(ExecError::MinimalIf));
+        assert_eq!(run(ExecCtx::SegwitV0, false, vec![vec![2]]), None);
+        assert_eq!(run(ExecCtx::Legacy, true, vec![vec![2]]), None);
+
+        let res = run(ExecCtx::Tapscript, false, vec![vec![2]]);
+        assert_eq!(res, Some(ExecError::TapscriptMinimalIf));
+    }
+
+    fn p2wsh_tx(witness_script: &Script) -> TxTemplate {
+        let mut tx = tx_template();
+        tx.prevouts[0] = TxOut {
+            value: Amount::from_sat(1000),
+            script_pubkey: ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+        };
+        tx
+    }
+
+    #[test]
+    fn p2wsh() {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = PublicKey::from_secret_key(&secp, &sk);
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&pk.serialize()))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let tx = p2wsh_tx(&script);
+
+        let sighash = SighashCache::new(&tx.tx)
+            .p2wsh_signature_hash(0, &script, Amount::from_sat(1000), EcdsaSighashType::All)
+            .unwrap();
+        let sig = secp.sign_ecdsa(&sighash.into(), &sk).serialize_der().to_vec();
+        let run = |opt: Options, witness: &[Vec<u8>], script: &Script| {
+            exec_p2wsh(tx.clone(), &Witness::from_slice(witness), script, opt)
+        };
+
+        let valid = [&sig[..], &[EcdsaSighashType::All as u8][..]].concat();
+        assert!(run(Options::default(), &[valid], &script).is_ok());
+
+        // The signature commits to SIGHASH_ALL, not SIGHASH_NONE.
+        let invalid = [&sig[..], &[EcdsaSighashType::None as u8][..]].concat();
+        let res = run(Options::default(), &[invalid], &script);
+        assert_eq!(res, Err(Error::Exec(ExecError::EvalFalse)));
+
+        let res = run(Options::default(), &[vec![]], &ops(&[OP_PUSHNUM_1]));
+        assert_eq!(res, Err(Error::Exec(ExecError::WitnessProgramMismatch)));
+
+        // Consensus limits can't be relaxed.
+        let opt = Options {
+            max_script_element_size: None,
+            ..Default::default()
+        };
+        let res = run(opt, &[vec![0; 521]], &script);
+        assert_eq!(res, Err(Error::Exec(ExecError::PushSize)));
+    }
+
+    #[test]
+    fn current_opcode() {
+        let script = ops(&[OP_PUSHNUM_1, OP_DROP]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert_eq!(exec.current_opcode(), Some(OP_PUSHNUM_1));
+        exec.exec_next().unwrap();
+        assert_eq!(exec.current_opcode(), Some(OP_DROP));
+        exec.exec_next().unwrap();
+        assert_eq!(exec.current_opcode(), None);
+        exec.run();
+        assert_eq!(exec.current_opcode(), None);
+    }
+
+    #[test]
+    fn wide64_mul() {
+        let mul = |x: i64, y: i64, expected: i64| {
+            script::Builder::new()
+                .push_int(x)
+                .push_int(y)
+                .push_opcode(OP_MUL)
+                .push_int(expected)
+                .push_opcode(OP_NUMEQUAL)
+                .into_script()
+        };
+        let wide = Options {
+            arithmetic_width: ScriptNumWidth::Wide64,
+            ..Default::default()
+        };
+
+        let script = mul(1 << 20, 1 << 20, 1 << 40);
+        assert!(exec_script(ExecCtx::Legacy, wide.clone(), script.clone(), vec![]).success);
+        let res = exec_script(ExecCtx::Tapscript, wide.clone(), script.clone(), vec![]);
+        assert!(res.success);
+        assert_eq!(res.opcode, None);
+        assert_eq!(stack_items(&res.final_stack), vec![vec![1]]);
+
+        let res = exec_script(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert_eq!(res.error, Some(ExecError::DisabledOpcode));
+
+        let res = exec_script(ExecCtx::Legacy, wide, mul(1 << 62, 4, 0), vec![]);
+        assert_eq!(res.error, Some(ExecError::ScriptIntNumericOverflow));
+    }
+
+    /// Run the script and return the error and the opcodes the pre-step hook saw.
+    fn hooked_run(ctx: ExecCtx, script: ScriptBuf) -> (Option<ExecError>, Vec<Opcode>) {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let mut exec = new_exec(ctx, Options::default(), script, vec![]);
+        let hook_seen = seen.clone();
+        exec.set_pre_step_hook(Box::new(move |op: Opcode, _: usize, _: &Stack| {
+            hook_seen.borrow_mut().push(op);
+        }));
+        let error = exec.run().error.clone();
+        let seen = seen.borrow().clone();
+        (error, seen)
+    }
+
+    #[test]
+    fn pre_step_hook() {
+        let script = ops(&[OP_PUSHNUM_1, OP_IF, OP_PUSHNUM_2, OP_ELSE, OP_PUSHNUM_3, OP_ENDIF]);
+        let (error, seen) = hooked_run(ExecCtx::Legacy, script);
+        assert_eq!(error, None);
+        assert_eq!(seen, vec![OP_PUSHNUM_1, OP_IF, OP_PUSHNUM_2, OP_ELSE, OP_ENDIF]);
+    }
+
+    #[test]
+    fn pre_step_hook_before_checks() {
+        let (error, seen) = hooked_run(ExecCtx::Legacy, ops(&[OP_PUSHNUM_1, OP_2MUL]));
+        assert_eq!(error, Some(ExecError::DisabledOpcode));
+        assert_eq!(seen, vec![OP_PUSHNUM_1, OP_2MUL]);
+
+        let (error, seen) = hooked_run(ExecCtx::Legacy, ops(&[OP_RESERVED]));
+        assert_eq!(error, Some(ExecError::Debug));
+        assert_eq!(seen, vec![OP_RESERVED]);
+
+        let script = script::Builder::new().push_slice(push_bytes(&[1; 521])).into_script();
+        let (error, seen) = hooked_run(ExecCtx::Legacy, script);
+        assert_eq!(error, Some(ExecError::PushSize));
+        assert_eq!(seen, vec![OP_PUSHDATA2]);
+
+        let (error, seen) = hooked_run(ExecCtx::Legacy, ops(&[OP_NOP; 202]));
+        assert_eq!(error, Some(ExecError::OpCount));
+        assert_eq!(seen.len(), 202);
+
+        // Not called in non-taken branches, even if the instruction fails.
+        let (error, seen) = hooked_run(ExecCtx::Legacy, ops(&[OP_PUSHBYTES_0, OP_IF, OP_2MUL]));
+        assert_eq!(error, Some(ExecError::DisabledOpcode));
+        assert_eq!(seen, vec![OP_PUSHBYTES_0, OP_IF]);
+
+        // Nor for conditionals nested in non-taken branches.
+        let nested = [OP_PUSHNUM_1, OP_IF, OP_ELSE, OP_ENDIF];
+        let script = ops(&[&[OP_PUSHBYTES_0, OP_IF][..], &nested[..], &[OP_ENDIF][..]].concat());
+        let (error, seen) = hooked_run(ExecCtx::Legacy, script);
+        assert_eq!(error, Some(ExecError::EvalFalse));
+        assert_eq!(seen, vec![OP_PUSHBYTES_0, OP_IF, OP_ENDIF]);
+    }
+
+    fn cat_script() -> ScriptBuf {
+        script::Builder::new()
+            .push_slice(push_bytes(&[0x20, 0x21]))
+            .push_slice(push_bytes(&[0x22]))
+            .push_opcode(OP_CAT)
+            .push_slice(push_bytes(&[0x20, 0x21, 0x22]))
+            .push_opcode(OP_EQUAL)
+            .into_script()
+    }
+
+    fn experimental(experimental: Experimental) -> Options {
+        Options {
+            experimental: experimental,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn experimental_per_context() {
+        assert!(exec_script(ExecCtx::Tapscript, Options::default(), cat_script(), vec![]).success);
+        let res = exec_script(ExecCtx::Legacy, Options::default(), cat_script(), vec![]);
+        assert_eq!(res.error, Some(ExecError::DisabledOpcode));
+        let res = exec_script(ExecCtx::SegwitV0, Options::default(), cat_script(), vec![]);
+        assert_eq!(res.error, Some(ExecError::DisabledOpcode));
+
+        let default = Options::default().experimental;
+        let opt = experimental(Experimental {
+            legacy: true,
+            ..default.clone()
+        });
+        assert!(exec_script(ExecCtx::Legacy, opt, cat_script(), vec![]).success);
+
+        // When not enabled, OP_CAT is an OP_SUCCESSx in Tapscript.
+        let opt = experimental(Experimental {
+            tapscript: false,
+            ..default.clone()
+        });
+        let res = exec_script(ExecCtx::Tapscript, opt, cat_script(), vec![]);
+        assert!(res.success);
+        assert_eq!(res.opcode, Some(OP_CAT));
+
+        let opt = experimental(Experimental {
+            op_cat: false,
+            ..default
+        });
+        let res = exec_script(ExecCtx::Tapscript, opt, cat_script(), vec![]);
+        assert_eq!(res.opcode, Some(OP_CAT));
+    }
+
+    #[test]
+    fn experimental_splice_and_bitwise() {
+        let opt = experimental(Experimental {
+            op_splice: true,
+            op_bitwise: true,
+            ..Options::default().experimental
+        });
+        let check = |args: script::Builder, op: Opcode, expected: &[u8]| {
+            let script = args
+                .push_opcode(op)
+                .push_slice(push_bytes(expected))
+                .push_opcode(OP_EQUAL)
+                .into_script();
+            assert!(exec_script(ExecCtx::Tapscript, opt.clone(), script, vec![]).success);
+        };
+        let data = || script::Builder::new().push_slice(push_bytes(&[1, 2, 3, 4, 5]));
+
+        check(data().push_int(1).push_int(3), OP_SUBSTR, &[2, 3, 4]);
+        check(data().push_int(2), OP_LEFT, &[1, 2]);
+        check(data().push_int(2), OP_RIGHT, &[4, 5]);
+        let xor = script::Builder::new()
+            .push_slice(push_bytes(&[0xf0, 0x0f]))
+            .push_slice(push_bytes(&[0xff, 0xff]));
+        check(xor, OP_XOR, &[0x0f, 0xf0]);
+    }
+
+    #[test]
+    fn stack_peaks() {
+        let script = ops(&[
+            OP_PUSHNUM_1,
+            OP_PUSHNUM_2,
+            OP_PUSHNUM_3,
+            OP_TOALTSTACK,
+            OP_TOALTSTACK,
+            OP_PUSHNUM_4,
+            OP_DROP,
+            OP_FROMALTSTACK,
+            OP_FROMALTSTACK,
+            OP_2DROP,
+        ]);
+        let mut exec = new_exec(ExecCtx::Legacy, Options::default(), script, vec![]);
+        assert!(exec.run().success);
+        assert_eq!(exec.stats().max_nb_stack_items, 4);
+        assert_eq!(exec.stats().max_nb_altstack_items, 2);
+    }
+
+    #[test]
+    fn no_error_position_at_end_of_script() {
+        let opt = Options {
+            require_clean_stack: true,
+            ..Default::default()
+        };
+        let script = ops(&[OP_PUSHNUM_1, OP_PUSHNUM_1]);
+        let mut exec = new_exec(ExecCtx::Legacy, opt, script, vec![]);
+        assert_eq!(exec.run().error, Some(ExecError::CleanStack));
+        assert_eq!(exec.last_error_position(), None);
+
+        let script = ops(&[OP_PUSHBYTES_0]);
+        let mut exec = new_exec(ExecCtx::Tapscript, Options::default(), script, vec![]);
+        assert_eq!(exec.run().error, Some(ExecError::EvalFalse));
+        assert_eq!(exec.last_error_position(), None);
+    }
+
+    #[test]
+    fn parse_hex() {
+        let script = parse_hex_script("0102ab51").unwrap();
+        assert_eq!(script.as_bytes(), &[0x01, 0x02, 0xab, 0x51]);
+
+        // OP_1, then OP_PUSHDATA1 declaring 5 bytes but only 2 remain.
+        let res = parse_hex_script("514c050102");
+        assert_eq!(res, Err(ParseHexError::TruncatedPush { offset: 1 }));
+        // OP_PUSHBYTES_5 with only 2 bytes.
+        let res = parse_hex_script("51050102");
+        assert_eq!(res, Err(ParseHexError::TruncatedPush { offset: 1 }));
+        assert!(matches!(parse_hex_script("zz"), Err(ParseHexError::InvalidHex(_))));
+        assert!(matches!(parse_hex_script("515"), Err(ParseHexError::InvalidHex(_))));
+
+        let res = Exec::from_hex(ExecCtx::Legacy, Options::default(), tx_template(), "4c", vec![]);
+        assert_eq!(res.err(), Some(Error::ParseHex(ParseHexError::TruncatedPush { offset: 0 })));
+        let mut exec =
+            Exec::from_hex(ExecCtx::Legacy, Options::default(), tx_template(), "515193", vec![])
+                .unwrap();
+        assert!(exec.run().success);
+    }
+
+    fn multisig_script(nb_sigs: i64, key_seeds: &[u8]) -> ScriptBuf {
+        let secp = Secp256k1::new();
+        let mut builder = script::Builder::new().push_int(nb_sigs);
+        for seed in key_seeds {
+            let sk = SecretKey::from_slice(&[*seed; 32]).unwrap();
+            let pk = PublicKey::from_secret_key(&secp, &sk);
+            builder = builder.push_slice(push_bytes(&pk.serialize()));
+        }
+        builder.push_int(key_seeds.len() as i64).push_opcode(OP_CHECKMULTISIG).into_script()
+    }
+
+    /// A legacy SIGHASH_ALL signature over the whole script.
+    fn legacy_sig(script: &Script, key_seed: u8) -> Vec<u8> {
+        let secp = Secp256k1::new();
+        let sk = SecretKey::from_slice(&[key_seed; 32]).unwrap();
+        let sighash =
+            SighashCache::new(&tx_template().tx).legacy_signature_hash(0, script, 1).unwrap();
+        let sig = secp.sign_ecdsa(&sighash.into(), &sk).serialize_der();
+        [&sig[..], &[EcdsaSighashType::All as u8][..]].concat()
+    }
+
+    fn run_legacy(opt: Options, script: &ScriptBuf, stack: Vec<Vec<u8>>) -> ExecutionResult {
+        let mut exec =
+            Exec::new(ExecCtx::Legacy, opt, tx_template(), script.clone(), stack).unwrap();
+        exec.run().clone()
+    }
+
+    #[test]
+    fn checkmultisig_nulldummy() {
+        let script = multisig_script(1, &[1]);
+        let sig = legacy_sig(&script, 1);
+
+        let res = run_legacy(Options::default(), &script, vec![vec![], sig.clone()]);
+        assert!(res.success);
+
+        let res = run_legacy(Options::default(), &script, vec![vec![1], sig.clone()]);
+        assert_eq!(res.error, Some(ExecError::SigNullDummy));
+        let opt = Options {
+            verify_nulldummy: false,
+            ..Default::default()
+        };
+        assert!(run_legacy(opt, &script, vec![vec![1], sig.clone()]).success);
+
+        // Without any dummy element.
+        let res = run_legacy(Options::default(), &script, vec![sig]);
+        assert_eq!(res.error, Some(ExecError::InvalidStackOperation));
+    }
+
+    #[test]
+    fn checkmultisig_order() {
+        let script = multisig_script(2, &[1, 2, 3]);
+        let (sig1, sig3) = (legacy_sig(&script, 1), legacy_sig(&script, 3));
+
+        let res = run_legacy(Options::default(), &script, vec![vec![], sig1.clone(), sig3.clone()]);
+        assert!(res.success);
+        // Signatures must be in the same order as the keys.
+        let res = run_legacy(Options::default(), &script, vec![vec![], sig3, sig1]);
+        assert_eq!(res.error, Some(ExecError::EvalFalse));
+    }
+
+    #[test]
+    fn checkmultisig_errors() {
+        let script = script::Builder::new().push_int(21).push_opcode(OP_CHECKMULTISIG);
+        let res = exec_script(ExecCtx::Legacy, Options::default(), script.into_script(), vec![]);
+        assert_eq!(res.error, Some(ExecError::PubkeyCount));
+
+        // Two signatures for one key.
+        let script = multisig_script(2, &[1]);
+        let res = exec_script(ExecCtx::Legacy, Options::default(), script.clone(), vec![]);
+        assert_eq!(res.error, Some(ExecError::SigCount));
+
+        let res = exec_script(ExecCtx::Tapscript, Options::default(), script, vec![]);
+        assert_eq!(res.error, Some(ExecError::TapscriptCheckMultiSig));
+    }
+}
@@ -0,0 +1,73 @@
+use core::ops::Deref;
+
+use bitcoin::script;
+
+/// Whether a stack item is true, like Core's CastToBool.
+///
+/// This is the function the interpreter uses for OP_IF, OP_VERIFY and the
+/// final stack check. Empty items and items of only zero bytes, optionally
+/// followed by a last 0x80 byte for negative zero, are false. Items don't
+/// have to be minimally encoded.
+pub use bitcoin::script::read_scriptbool;
+
+/// A script number encoded without allocating, see [encode].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptNum {
+    buf: [u8; 8],
+    len: usize,
+}
+
+impl Deref for ScriptNum {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for ScriptNum {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+/// Encode a number the same way as [script::scriptint_vec], but into a fixed
+/// buffer instead of a [Vec].
+///
+/// Panics for [i64::MIN], the only value that doesn't fit in 8 bytes.
+pub fn encode(n: i64) -> ScriptNum {
+    let mut buf = [0; 8];
+    let len = script::write_scriptint(&mut buf, n);
+    ScriptNum { buf: buf, len: len }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_matches_scriptint_vec() {
+        let mut values = vec![0, 1, -1, 127, 128, -128, 255, 256, 32767, 32768, -32768];
+        values.extend([i32::MAX as i64, i32::MIN as i64, u32::MAX as i64, 1 << 40]);
+        values.extend([i64::MAX, i64::MAX - 1, i64::MIN + 1, i64::MIN + 2, -(1 << 55)]);
+        for n in values {
+            assert_eq!(encode(n).as_ref(), &script::scriptint_vec(n)[..], "{}", n);
+            assert_eq!(&*encode(n), &script::scriptint_vec(n)[..], "{}", n);
+        }
+        assert!(encode(0).is_empty());
+        assert_eq!(encode(i64::MAX).len(), 8);
+    }
+
+    #[test]
+    fn scriptbool() {
+        assert!(!read_scriptbool(&[]));
+        assert!(!read_scriptbool(&[0x00]));
+        assert!(!read_scriptbool(&[0x00, 0x00, 0x80]));
+        assert!(!read_scriptbool(&[0x80]));
+        assert!(read_scriptbool(&[0x00, 0x01]));
+
+        assert!(read_scriptbool(&[0x01]));
+        assert!(read_scriptbool(&[0x80, 0x00]));
+        assert!(read_scriptbool(&[0x81]));
+    }
+}
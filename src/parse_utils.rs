@@ -0,0 +1,183 @@
+use bitcoin::hex::DisplayHex;
+use bitcoin::opcodes::{all::*, Class, ClassifyContext, Opcode};
+use bitcoin::script::{self, Instruction, Script};
+
+/// Disassemble a script into the ASM syntax accepted by
+/// [bitcoin::ScriptBuf::parse_asm].
+///
+/// The empty push is written as OP_0, OP_1NEGATE and OP_1 to OP_16 by their
+/// number, other data pushes as hex and opcodes by their full name. A push
+/// whose hex consists of digits only would be parsed as a number, so it is
+/// written as the number it encodes when that gives the same bytes back.
+///
+/// Parsing the result gives back the original script if all its pushes are
+/// minimal. Scripts that fail to parse end in `<unexpected end>`.
+pub fn to_asm(script: &Script) -> String {
+    let mut ret = Vec::new();
+    for ins in script.instructions() {
+        match ins {
+            Ok(Instruction::PushBytes(p)) if p.is_empty() => ret.push("OP_0".to_owned()),
+            Ok(Instruction::PushBytes(p)) => ret.push(push_to_asm(p.as_bytes())),
+            Ok(Instruction::Op(op)) => match op.classify(ClassifyContext::Legacy) {
+                Class::PushNum(-1) => ret.push("OP_1NEGATE".to_owned()),
+                Class::PushNum(n) => ret.push(format!("OP_{}", n)),
+                // Displayed as OP_CLTV and OP_CSV, which parse_asm doesn't accept.
+                _ if op == OP_CLTV => ret.push("OP_CHECKLOCKTIMEVERIFY".to_owned()),
+                _ if op == OP_CSV => ret.push("OP_CHECKSEQUENCEVERIFY".to_owned()),
+                _ => ret.push(op.to_string()),
+            },
+            Err(_) => {
+                ret.push("<unexpected end>".to_owned());
+                break;
+            }
+        }
+    }
+    ret.join(" ")
+}
+
+/// The push opcode with the shortest encoding for a data push of [len] bytes.
+///
+/// This only looks at the length: single byte pushes of 1 to 16 and 0x81 are
+/// minimally written as OP_1 to OP_16 and OP_1NEGATE instead. Returns [None]
+/// if the length doesn't fit the 4-byte length of OP_PUSHDATA4.
+pub fn minimal_push_opcode(len: usize) -> Option<Opcode> {
+    match len {
+        0..=75 => Some(Opcode::from(len as u8)),
+        76..=0xff => Some(OP_PUSHDATA1),
+        0x100..=0xffff => Some(OP_PUSHDATA2),
+        _ if u32::try_from(len).is_ok() => Some(OP_PUSHDATA4),
+        _ => None,
+    }
+}
+
+fn push_to_asm(data: &[u8]) -> String {
+    let hex = data.to_lower_hex_string();
+    if hex.bytes().all(|c| c.is_ascii_digit()) {
+        // parse_asm pushes numbers with push_int, which only uses a data
+        // push outside the range of OP_1NEGATE to OP_16.
+        if let Ok(n) = script::read_scriptint_size(data, 8, true) {
+            if !(-1..=16).contains(&n) {
+                return n.to_string();
+            }
+        }
+    }
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bitcoin::ScriptBuf;
+
+    use crate::tests::push_bytes;
+
+    fn round_trip(script: &ScriptBuf) {
+        let asm = to_asm(script);
+        assert_eq!(ScriptBuf::parse_asm(&asm).unwrap(), *script, "{}", asm);
+    }
+
+    #[test]
+    fn to_asm_round_trip() {
+        let p2pkh = script::Builder::new()
+            .push_opcode(OP_DUP)
+            .push_opcode(OP_HASH160)
+            .push_slice(push_bytes(&[0xab; 20]))
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        round_trip(&p2pkh);
+
+        let multisig = script::Builder::new()
+            .push_int(2)
+            .push_slice(push_bytes(&[2; 33]))
+            .push_slice(push_bytes(&[3; 33]))
+            .push_int(2)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        round_trip(&multisig);
+
+        let htlc = script::Builder::new()
+            .push_opcode(OP_IF)
+            .push_opcode(OP_SHA256)
+            .push_slice(push_bytes(&[0xcd; 32]))
+            .push_opcode(OP_EQUALVERIFY)
+            .push_opcode(OP_ELSE)
+            .push_int(144)
+            .push_opcode(OP_CSV)
+            .push_opcode(OP_DROP)
+            .push_opcode(OP_ENDIF)
+            .push_slice(push_bytes(&[0xef; 32]))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        round_trip(&htlc);
+        assert!(to_asm(&htlc).contains("OP_CHECKSEQUENCEVERIFY"));
+
+        let numbers = (-1..=16)
+            .chain([17, 1000, -1000, i32::MAX as i64])
+            .fold(script::Builder::new(), |b, n| b.push_int(n))
+            .into_script();
+        round_trip(&numbers);
+    }
+
+    #[test]
+    fn to_asm_pushes() {
+        let empty = script::Builder::new().push_slice(push_bytes(&[])).into_script();
+        assert_eq!(to_asm(&empty), "OP_0");
+        round_trip(&empty);
+
+        let leading_zero = script::Builder::new().push_slice(push_bytes(&[0, 0xab])).into_script();
+        assert_eq!(to_asm(&leading_zero), "00ab");
+        round_trip(&leading_zero);
+        // Only digits, so written as the number 0x1100.
+        let digits = script::Builder::new().push_slice(push_bytes(&[0, 0x11])).into_script();
+        assert_eq!(to_asm(&digits), "4352");
+        round_trip(&digits);
+
+        let pushdata1 = script::Builder::new().push_slice(push_bytes(&[0xaa; 76])).into_script();
+        assert_eq!(pushdata1.as_bytes()[0], OP_PUSHDATA1.to_u8());
+        round_trip(&pushdata1);
+        let pushdata2 = script::Builder::new().push_slice(push_bytes(&[0xaa; 256])).into_script();
+        assert_eq!(pushdata2.as_bytes()[0], OP_PUSHDATA2.to_u8());
+        round_trip(&pushdata2);
+    }
+
+    #[test]
+    fn to_asm_numbers() {
+        let script = script::Builder::new()
+            .push_opcode(OP_PUSHNUM_NEG1)
+            .push_opcode(OP_PUSHNUM_1)
+            .push_opcode(OP_PUSHNUM_16)
+            .into_script();
+        assert_eq!(to_asm(&script), "OP_1NEGATE OP_1 OP_16");
+    }
+
+    #[test]
+    fn minimal_push_opcodes() {
+        assert_eq!(minimal_push_opcode(0), Some(OP_PUSHBYTES_0));
+        assert_eq!(minimal_push_opcode(1), Some(OP_PUSHBYTES_1));
+        assert_eq!(minimal_push_opcode(75), Some(OP_PUSHBYTES_75));
+        assert_eq!(minimal_push_opcode(76), Some(OP_PUSHDATA1));
+        assert_eq!(minimal_push_opcode(255), Some(OP_PUSHDATA1));
+        assert_eq!(minimal_push_opcode(256), Some(OP_PUSHDATA2));
+        assert_eq!(minimal_push_opcode(65535), Some(OP_PUSHDATA2));
+        assert_eq!(minimal_push_opcode(65536), Some(OP_PUSHDATA4));
+        assert_eq!(minimal_push_opcode(u32::MAX as usize), Some(OP_PUSHDATA4));
+        if let Ok(len) = usize::try_from(u32::MAX as u64 + 1) {
+            assert_eq!(minimal_push_opcode(len), None);
+        }
+
+        // The same opcodes as the builder uses.
+        for len in [0, 75, 76, 255, 256] {
+            let script = script::Builder::new().push_slice(push_bytes(&vec![0xaa; len]));
+            let op = script.into_script().as_bytes()[0];
+            assert_eq!(minimal_push_opcode(len), Some(Opcode::from(op)), "{}", len);
+        }
+    }
+
+    #[test]
+    fn to_asm_truncated() {
+        let script = ScriptBuf::from_bytes(vec![OP_DUP.to_u8(), OP_PUSHBYTES_2.to_u8(), 0x01]);
+        assert_eq!(to_asm(&script), "OP_DUP <unexpected end>");
+    }
+}
@@ -1,5 +1,5 @@
 use bitcoin::secp256k1::{self, PublicKey, XOnlyPublicKey};
-use bitcoin::sighash::{Annex, EcdsaSighashType, Prevouts, TapSighashType};
+use bitcoin::sighash::{Annex, EcdsaSighashType, Prevouts, SighashCache, TapSighashType};
 
 use crate::*;
 
@@ -7,50 +7,315 @@ lazy_static::lazy_static! {
     static ref SECP: secp256k1::Secp256k1<secp256k1::All> = secp256k1::Secp256k1::new();
 }
 
-impl Exec {
-    pub fn check_sig_ecdsa(&mut self, sig: &[u8], pk: &[u8], script_code: &[u8]) -> bool {
+/// Verifies signatures on behalf of the interpreter.
+///
+/// The interpreter takes care of the encoding rules of each context and only
+/// asks the checker to do the actual verification. Everything that depends on
+/// the state of the execution is passed in on each call.
+pub trait SignatureChecker {
+    /// Check a DER-encoded ECDSA signature, without the trailing sighash type byte.
+    ///
+    /// The [ctx] is either [ExecCtx::Legacy] or [ExecCtx::SegwitV0] and decides
+    /// which signature hash is used.
+    fn check_ecdsa(
+        &mut self,
+        ctx: ExecCtx,
+        sig: &[u8],
+        pk: &[u8],
+        script_code: &Script,
+        sighash_type: u32,
+    ) -> bool;
+
+    /// Check a 64-byte BIP-340 signature against a 32-byte public key.
+    ///
+    /// The [leaf_hash], [annex] and [codeseparator_pos] are committed to by
+    /// the BIP 342 signature hash.
+    fn check_schnorr(
+        &mut self,
+        sig: &[u8],
+        pk: &[u8],
+        sighash_type: TapSighashType,
+        leaf_hash: TapLeafHash,
+        annex: Option<&[u8]>,
+        codeseparator_pos: u32,
+    ) -> bool;
+}
+
+/// The default [SignatureChecker] which verifies signatures against
+/// the transaction of a [TxTemplate].
+pub struct TxSignatureChecker {
+    sighashcache: SighashCache<Transaction>,
+    prevouts: Vec<TxOut>,
+    input_idx: usize,
+}
+
+impl TxSignatureChecker {
+    pub fn new(tx: &TxTemplate) -> TxSignatureChecker {
+        TxSignatureChecker {
+            sighashcache: SighashCache::new(tx.tx.clone()),
+            prevouts: tx.prevouts.clone(),
+            input_idx: tx.input_idx,
+        }
+    }
+}
+
+impl SignatureChecker for TxSignatureChecker {
+    fn check_ecdsa(
+        &mut self,
+        ctx: ExecCtx,
+        sig: &[u8],
+        pk: &[u8],
+        script_code: &Script,
+        sighash_type: u32,
+    ) -> bool {
         let pk = match PublicKey::from_slice(pk) {
             Ok(pk) => pk,
             Err(_) => return false,
         };
 
-        if sig.is_empty() {
-            return false;
-        }
-
-        let hashtype = *sig.last().unwrap();
-        let sig = match secp256k1::ecdsa::Signature::from_der(&sig[0..sig.len() - 1]) {
+        // Like Core, parse leniently and accept high S values. The encoding
+        // rules are enforced by the interpreter according to the options.
+        let mut sig = match secp256k1::ecdsa::Signature::from_der_lax(sig) {
             Ok(s) => s,
             Err(_) => return false,
         };
+        sig.normalize_s();
 
-        let sighash = if self.ctx == ExecCtx::SegwitV0 {
-            self.sighashcache
-                .p2wsh_signature_hash(
-                    self.tx.input_idx,
-                    Script::from_bytes(script_code),
-                    self.tx.prevouts[self.tx.input_idx].value,
+        // Both only fail if the input index is out of bounds.
+        let sighash = match ctx {
+            ExecCtx::SegwitV0 => {
+                let value = match self.prevouts.get(self.input_idx) {
+                    Some(prevout) => prevout.value,
+                    None => return false,
+                };
+                match self.sighashcache.p2wsh_signature_hash(
+                    self.input_idx,
+                    script_code,
+                    value,
                     //TODO(stevenroose) this might not actually emulate consensus behavior
-                    EcdsaSighashType::from_consensus(hashtype as u32),
-                )
-                .expect("only happens on prevout index out of bounds")
-                .into()
-        } else if self.ctx == ExecCtx::Legacy {
-            self.sighashcache
-                .legacy_signature_hash(
-                    self.tx.input_idx,
-                    Script::from_bytes(script_code),
-                    hashtype as u32,
-                )
-                .expect("TODO(stevenroose) seems to only happen if prevout index out of bound")
-                .into()
-        } else {
-            unreachable!();
+                    EcdsaSighashType::from_consensus(sighash_type),
+                ) {
+                    Ok(h) => h.into(),
+                    Err(_) => return false,
+                }
+            }
+            ExecCtx::Legacy => {
+                match self.sighashcache.legacy_signature_hash(
+                    self.input_idx,
+                    script_code,
+                    sighash_type,
+                ) {
+                    Ok(h) => h.into(),
+                    Err(_) => return false,
+                }
+            }
+            // There are no ECDSA signatures in Tapscript.
+            ExecCtx::Tapscript => return false,
         };
 
         SECP.verify_ecdsa(&sighash, &sig, &pk).is_ok()
     }
 
+    fn check_schnorr(
+        &mut self,
+        sig: &[u8],
+        pk: &[u8],
+        sighash_type: TapSighashType,
+        leaf_hash: TapLeafHash,
+        annex: Option<&[u8]>,
+        codeseparator_pos: u32,
+    ) -> bool {
+        let pk = match XOnlyPublicKey::from_slice(pk) {
+            Ok(pk) => pk,
+            Err(_) => return false,
+        };
+
+        let sig = match secp256k1::schnorr::Signature::from_slice(sig) {
+            Ok(s) => s,
+            Err(_) => return false,
+        };
+
+        let annex = match annex.map(Annex::new).transpose() {
+            Ok(a) => a,
+            Err(_) => return false,
+        };
+        let sighash = match self.sighashcache.taproot_signature_hash(
+            self.input_idx,
+            &Prevouts::All(&self.prevouts),
+            annex,
+            Some((leaf_hash, codeseparator_pos)),
+            sighash_type,
+        ) {
+            Ok(h) => h,
+            // Only happens if the input index or the prevouts are invalid.
+            Err(_) => return false,
+        };
+
+        SECP.verify_schnorr(&sig, &sighash.into(), &pk).is_ok()
+    }
+}
+
+/// A [SignatureChecker] that considers every signature valid.
+///
+/// Useful for testing and fuzzing. The interpreter still checks the encoding
+/// of signatures before asking the checker, so dummy ECDSA signatures fail
+/// with the default options unless they are strict DER. Turn off
+/// [Options::verify_dersig], [Options::verify_low_s] and
+/// [Options::verify_strictenc] to accept any non-empty signature.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysValidChecker;
+
+impl SignatureChecker for AlwaysValidChecker {
+    fn check_ecdsa(&mut self, _: ExecCtx, _: &[u8], _: &[u8], _: &Script, _: u32) -> bool {
+        true
+    }
+
+    fn check_schnorr(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: TapSighashType,
+        _: TapLeafHash,
+        _: Option<&[u8]>,
+        _: u32,
+    ) -> bool {
+        true
+    }
+}
+
+/// A [SignatureChecker] that considers every signature invalid.
+///
+/// Useful for testing and fuzzing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AlwaysInvalidChecker;
+
+impl SignatureChecker for AlwaysInvalidChecker {
+    fn check_ecdsa(&mut self, _: ExecCtx, _: &[u8], _: &[u8], _: &Script, _: u32) -> bool {
+        false
+    }
+
+    fn check_schnorr(
+        &mut self,
+        _: &[u8],
+        _: &[u8],
+        _: TapSighashType,
+        _: TapLeafHash,
+        _: Option<&[u8]>,
+        _: u32,
+    ) -> bool {
+        false
+    }
+}
+
+/// Check that [sig] is a strict DER signature followed by a sighash type byte.
+///
+/// Equivalent to Bitcoin Core's `IsValidSignatureEncoding`.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    // Format: 0x30 [total-length] 0x02 [R-length] [R] 0x02 [S-length] [S] [sighash]
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+
+    // R must be a positive integer without excess padding.
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+
+    // Same for S.
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+
+    true
+}
+
+/// Check that the S value of a signature with sighash byte is in the lower half.
+fn is_low_s(sig: &[u8]) -> bool {
+    let sig = match secp256k1::ecdsa::Signature::from_der_lax(&sig[0..sig.len() - 1]) {
+        Ok(s) => s,
+        Err(_) => return false,
+    };
+    let mut normalized = sig;
+    normalized.normalize_s();
+    normalized == sig
+}
+
+/// Check that the sighash byte of a signature is one of ALL, NONE or SINGLE,
+/// optionally with ANYONECANPAY.
+fn is_defined_hashtype(sig: &[u8]) -> bool {
+    let hashtype = sig.last().expect("non-empty") & !0x80;
+    hashtype >= EcdsaSighashType::All as u8 && hashtype <= EcdsaSighashType::Single as u8
+}
+
+impl Exec {
+    /// Check the encoding of an ECDSA signature according to the options.
+    ///
+    /// The empty signature is always allowed.
+    pub(crate) fn check_signature_encoding(&self, sig: &[u8]) -> Result<(), ExecError> {
+        if sig.is_empty() {
+            return Ok(());
+        }
+
+        let strict = self.opt.verify_dersig || self.opt.verify_low_s || self.opt.verify_strictenc;
+        if strict && !is_valid_signature_encoding(sig) {
+            return Err(ExecError::SigDer);
+        }
+        if self.opt.verify_low_s && !is_low_s(sig) {
+            return Err(ExecError::SigHighS);
+        }
+        if self.opt.verify_strictenc && !is_defined_hashtype(sig) {
+            return Err(ExecError::SigHashtype);
+        }
+        Ok(())
+    }
+
+    /// Check the encoding of an ECDSA public key according to the options.
+    pub(crate) fn check_pubkey_encoding(&self, pk: &[u8]) -> Result<(), ExecError> {
+        if self.opt.verify_strictenc {
+            let valid = match pk.first() {
+                Some(0x02) | Some(0x03) => pk.len() == 33,
+                Some(0x04) => pk.len() == 65,
+                _ => false,
+            };
+            if !valid {
+                return Err(ExecError::PubkeyType);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn check_sig_ecdsa(&mut self, sig: &[u8], pk: &[u8], script_code: &[u8]) -> bool {
+        if sig.is_empty() {
+            return false;
+        }
+
+        let hashtype = *sig.last().unwrap();
+        self.sig_checker.check_ecdsa(
+            self.ctx,
+            &sig[0..sig.len() - 1],
+            pk,
+            Script::from_bytes(script_code),
+            hashtype as u32,
+        )
+    }
+
     /// [pk] should be passed as 32-bytes.
     pub fn check_sig_schnorr(&mut self, sig: &[u8], pk: &[u8]) -> Result<(), ExecError> {
         assert_eq!(pk.len(), 32);
@@ -59,43 +324,229 @@ impl Exec {
             return Err(ExecError::SchnorrSigSize);
         }
 
-        let pk = XOnlyPublicKey::from_slice(pk).expect("TODO(stevenroose) what to do here?");
         let (sig, hashtype) = if sig.len() == 65 {
             let b = *sig.last().unwrap();
-            let sig = secp256k1::schnorr::Signature::from_slice(&sig[0..sig.len() - 1])
-                .map_err(|_| ExecError::SchnorrSig)?;
-
             if b == TapSighashType::Default as u8 {
                 return Err(ExecError::SchnorrSigHashtype);
             }
             //TODO(stevenroose) core does not error here
             let sht =
                 TapSighashType::from_consensus_u8(b).map_err(|_| ExecError::SchnorrSigHashtype)?;
-            (sig, sht)
+            (&sig[0..sig.len() - 1], sht)
         } else {
-            let sig = secp256k1::schnorr::Signature::from_slice(sig)
-                .map_err(|_| ExecError::SchnorrSig)?;
             (sig, TapSighashType::Default)
         };
 
-        let (leaf_hash, annex) = self.tx.taproot_annex_scriptleaf.as_ref().unwrap();
-        let sighash = self
-            .sighashcache
-            .taproot_signature_hash(
-                self.tx.input_idx,
-                &Prevouts::All(&self.tx.prevouts),
-                annex
-                    .as_ref()
-                    .map(|a| Annex::new(a).expect("we checked annex prefix before")),
-                Some((*leaf_hash, self.last_codeseparator_pos.unwrap_or(u32::MAX))),
-                hashtype,
-            )
-            .expect("TODO(stevenroose) seems to only happen if prevout index out of bound");
-
-        if SECP.verify_schnorr(&sig, &sighash.into(), &pk) != Ok(()) {
+        let (leaf_hash, annex) = match self.tx.taproot_annex_scriptleaf {
+            Some((leaf_hash, ref annex)) => (leaf_hash, annex.as_deref()),
+            // Always present in Tapscript, see [Exec::with_signature_checker].
+            None => return Err(ExecError::SchnorrSig),
+        };
+        let codeseparator_pos = self.last_codeseparator_pos.unwrap_or(u32::MAX);
+        let valid =
+            self.sig_checker.check_schnorr(sig, pk, hashtype, leaf_hash, annex, codeseparator_pos);
+        if !valid {
             return Err(ExecError::SchnorrSig);
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::tests::{push_bytes, tx_template};
+
+    fn tapscript_checksig(checker: Box<dyn SignatureChecker>) -> ExecutionResult {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[1; 32]))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let witness = vec![vec![1; 64]];
+        let opt = Options::default();
+        let ctx = ExecCtx::Tapscript;
+        let mut exec =
+            Exec::with_signature_checker(ctx, opt, tx_template(), script, witness, checker)
+                .unwrap();
+        exec.run().clone()
+    }
+
+    #[test]
+    fn custom_checker() {
+        assert!(tapscript_checksig(Box::new(AlwaysValidChecker)).success);
+        let res = tapscript_checksig(Box::new(AlwaysInvalidChecker));
+        assert_eq!(res.error, Some(ExecError::SchnorrSig));
+    }
+
+    #[test]
+    fn tx_checker_does_not_panic() {
+        use bitcoin::hex::FromHex;
+
+        // The x coordinate of the generator point.
+        let g = Vec::<u8>::from_hex(
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let pk = [&[0x02][..], &g[..]].concat();
+        // r = 1, s = 1
+        let sig = [0x30, 0x06, 0x02, 0x01, 0x01, 0x02, 0x01, 0x01];
+        let script = Script::from_bytes(&[]);
+
+        let mut checker = TxSignatureChecker::new(&tx_template());
+        assert!(!checker.check_ecdsa(ExecCtx::Tapscript, &sig, &pk, script, 1));
+
+        let leaf_hash = TapLeafHash::all_zeros();
+        let sht = TapSighashType::Default;
+        let invalid_annex = Some(&[0x00][..]);
+        assert!(!checker.check_schnorr(&[1; 64], &g, sht, leaf_hash, invalid_annex, u32::MAX));
+
+        let mut tx = tx_template();
+        tx.input_idx = 1;
+        let mut checker = TxSignatureChecker::new(&tx);
+        assert!(!checker.check_ecdsa(ExecCtx::Legacy, &sig, &pk, script, 1));
+        assert!(!checker.check_ecdsa(ExecCtx::SegwitV0, &sig, &pk, script, 1));
+        assert!(!checker.check_schnorr(&[1; 64], &g, sht, leaf_hash, None, u32::MAX));
+    }
+
+    /// A DER signature with the given R and S values and SIGHASH_ALL.
+    fn der(r: &[u8], s: &[u8]) -> Vec<u8> {
+        let mut ret = vec![0x30, (r.len() + s.len() + 4) as u8, 0x02, r.len() as u8];
+        ret.extend_from_slice(r);
+        ret.extend_from_slice(&[0x02, s.len() as u8]);
+        ret.extend_from_slice(s);
+        ret.push(0x01);
+        ret
+    }
+
+    #[test]
+    fn signature_encoding() {
+        let high = [&[0x00][..], &[0x80; 32][..]].concat();
+
+        assert!(is_valid_signature_encoding(&der(&[0x01], &[0x01])));
+        assert!(is_valid_signature_encoding(&der(&high, &high)));
+        // Padding is required if the highest bit is set.
+        assert!(is_valid_signature_encoding(&der(&[0x00, 0x80], &[0x01])));
+
+        // Too short and too long.
+        assert!(!is_valid_signature_encoding(&der(&[0x01], &[0x01])[..8]));
+        assert!(!is_valid_signature_encoding(&der(&[&[0x00][..], &high].concat(), &high)));
+
+        // Wrong compound tag, total length and integer tags.
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[0] = 0x31;
+        assert!(!is_valid_signature_encoding(&sig));
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[1] += 1;
+        assert!(!is_valid_signature_encoding(&sig));
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[2] = 0x03;
+        assert!(!is_valid_signature_encoding(&sig));
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[5] = 0x03;
+        assert!(!is_valid_signature_encoding(&sig));
+
+        // Length of R or S pointing past the signature.
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[3] = 0x05;
+        assert!(!is_valid_signature_encoding(&sig));
+        let mut sig = der(&[0x01], &[0x01]);
+        sig[6] = 0x02;
+        assert!(!is_valid_signature_encoding(&sig));
+
+        // Zero-length, negative and needlessly padded R.
+        assert!(!is_valid_signature_encoding(&der(&[], &[0x01, 0x01])));
+        assert!(!is_valid_signature_encoding(&der(&[0x80], &[0x01])));
+        assert!(!is_valid_signature_encoding(&der(&[0x00, 0x01], &[0x01])));
+
+        // Same for S.
+        assert!(!is_valid_signature_encoding(&der(&[0x01, 0x01], &[])));
+        assert!(!is_valid_signature_encoding(&der(&[0x01], &[0x80])));
+        assert!(!is_valid_signature_encoding(&der(&[0x01], &[0x00, 0x01])));
+
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let msg = secp256k1::Message::from_digest_slice(&[2; 32]).unwrap();
+        let mut sig = SECP.sign_ecdsa(&msg, &sk).serialize_der().to_vec();
+        sig.push(0x01);
+        assert!(is_valid_signature_encoding(&sig));
+    }
+
+    #[test]
+    fn always_valid_checker_encoding() {
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&[2; 33]))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let run = |opt: Options, sig: Vec<u8>| {
+            let checker = Box::new(AlwaysValidChecker);
+            let (ctx, tx, script) = (ExecCtx::Legacy, tx_template(), script.clone());
+            let mut exec =
+                Exec::with_signature_checker(ctx, opt, tx, script, vec![sig], checker).unwrap();
+            exec.run().clone()
+        };
+
+        let res = run(Options::default(), vec![1; 70]);
+        assert_eq!(res.error, Some(ExecError::SigDer));
+        assert!(run(Options::default(), der(&[0x01], &[0x01])).success);
+        let opt = Options {
+            verify_dersig: false,
+            ..Default::default()
+        };
+        assert!(run(opt, vec![1; 70]).success);
+    }
+
+    /// Replace the S value of a compact signature with n - S.
+    fn negate_s(compact: [u8; 64]) -> [u8; 64] {
+        // The order of the curve.
+        const N: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let mut ret = compact;
+        let mut borrow = 0;
+        for i in (0..32).rev() {
+            let d = N[i] as i16 - compact[32 + i] as i16 - borrow;
+            ret[32 + i] = d.rem_euclid(256) as u8;
+            borrow = (d < 0) as i16;
+        }
+        ret
+    }
+
+    #[test]
+    fn high_s() {
+        let secp = secp256k1::Secp256k1::new();
+        let sk = secp256k1::SecretKey::from_slice(&[1; 32]).unwrap();
+        let pk = PublicKey::from_secret_key(&secp, &sk);
+        let script = script::Builder::new()
+            .push_slice(push_bytes(&pk.serialize()))
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let tx = tx_template();
+        let sighash = SighashCache::new(&tx.tx).legacy_signature_hash(0, &script, 1).unwrap();
+        let low = secp.sign_ecdsa(&sighash.into(), &sk);
+        let high = secp256k1::ecdsa::Signature::from_compact(&negate_s(low.serialize_compact()))
+            .unwrap();
+        let with_hashtype = |sig: secp256k1::ecdsa::Signature| {
+            [&sig.serialize_der()[..], &[0x01][..]].concat()
+        };
+        let (low, high) = (with_hashtype(low), with_hashtype(high));
+        assert!(is_low_s(&low));
+        assert!(!is_low_s(&high));
+
+        let run = |verify_low_s: bool, sig: Vec<u8>| {
+            let opt = Options {
+                verify_low_s: verify_low_s,
+                ..Default::default()
+            };
+            let mut exec =
+                Exec::new(ExecCtx::Legacy, opt, tx.clone(), script.clone(), vec![sig]).unwrap();
+            exec.run().clone()
+        };
+        assert!(run(false, low.clone()).success);
+        assert!(run(true, low).success);
+        assert!(run(false, high.clone()).success);
+        assert_eq!(run(true, high).error, Some(ExecError::SigHighS));
+    }
+}
@@ -0,0 +1,115 @@
+use bitcoin::locktime::absolute::{self, LOCK_TIME_THRESHOLD};
+use bitcoin::locktime::relative;
+use bitcoin::Sequence;
+
+/// If this flag is set, a relative locktime is interpreted as a multiple of
+/// 512 seconds, otherwise as a number of blocks (BIP 68).
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+
+/// Interpret a script number as a BIP-65 absolute locktime.
+///
+/// Values below 500,000,000 are block heights, values at or above that
+/// threshold are UNIX timestamps. Returns [None] for negative values and
+/// values that don't fit the 32-bit nLockTime field.
+pub fn from_num_absolute(num: i64) -> Option<absolute::LockTime> {
+    let num = u32::try_from(num).ok()?;
+    if num < LOCK_TIME_THRESHOLD {
+        absolute::LockTime::from_height(num).ok()
+    } else {
+        absolute::LockTime::from_time(num).ok()
+    }
+}
+
+/// Encode a relative locktime as the script number that
+/// [relative::LockTime::from_num] decodes it from.
+///
+/// The 16-bit value is placed in the low bits and the type flag (bit 22) is
+/// set for time-based locktimes. The disable flag and all other bits above
+/// bit 22 are always zero.
+pub fn to_num(lock: relative::LockTime) -> i64 {
+    match lock {
+        relative::LockTime::Blocks(h) => h.value() as i64,
+        relative::LockTime::Time(t) => SEQUENCE_LOCKTIME_TYPE_FLAG | t.value() as i64,
+    }
+}
+
+/// Check a BIP-68 relative locktime required by a script against the
+/// nSequence of the spending input.
+///
+/// Both locktimes must be of the same type, either block-based or time-based,
+/// and the input's locktime must be at least the required one. Returns false
+/// when the input's nSequence doesn't encode a relative locktime.
+pub fn check_relative_locktime(script_lock: relative::LockTime, input_sequence: Sequence) -> bool {
+    let input_lock = match input_sequence.to_relative_lock_time() {
+        Some(lt) => lt,
+        None => return false,
+    };
+
+    match (script_lock, input_lock) {
+        (relative::LockTime::Blocks(h1), relative::LockTime::Blocks(h2)) => h1 <= h2,
+        (relative::LockTime::Time(t1), relative::LockTime::Time(t2)) => t1 <= t2,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_locktime_threshold() {
+        assert_eq!(from_num_absolute(0), Some(absolute::LockTime::ZERO));
+
+        let below = from_num_absolute(499_999_999).unwrap();
+        assert!(below.is_block_height());
+        assert_eq!(below, absolute::LockTime::from_height(499_999_999).unwrap());
+
+        let at = from_num_absolute(500_000_000).unwrap();
+        assert!(at.is_block_time());
+        assert_eq!(at, absolute::LockTime::from_time(500_000_000).unwrap());
+
+        let max = from_num_absolute(u32::MAX as i64).unwrap();
+        assert_eq!(max, absolute::LockTime::from_time(u32::MAX).unwrap());
+    }
+
+    #[test]
+    fn absolute_locktime_out_of_range() {
+        assert_eq!(from_num_absolute(-1), None);
+        assert_eq!(from_num_absolute(u32::MAX as i64 + 1), None);
+        assert_eq!(from_num_absolute(i64::MAX), None);
+    }
+
+    #[test]
+    fn relative_locktime_round_trip() {
+        let values = (0..=u16::MAX).step_by(97).chain([u16::MAX]);
+        for v in values {
+            for lock in [
+                relative::LockTime::from_height(v),
+                relative::LockTime::from_512_second_intervals(v),
+            ] {
+                let num = to_num(lock);
+                assert_eq!(num & !(SEQUENCE_LOCKTIME_TYPE_FLAG | 0xffff), 0);
+                assert_eq!(relative::LockTime::from_num(num), Some(lock));
+            }
+        }
+    }
+
+    #[test]
+    fn relative_locktime_check() {
+        let blocks = relative::LockTime::from_height(10);
+        let time = relative::LockTime::from_512_second_intervals(10);
+
+        assert!(check_relative_locktime(blocks, Sequence::from_height(10)));
+        assert!(check_relative_locktime(blocks, Sequence::from_height(11)));
+        assert!(!check_relative_locktime(blocks, Sequence::from_height(9)));
+        assert!(check_relative_locktime(time, Sequence::from_512_second_intervals(10)));
+        assert!(!check_relative_locktime(time, Sequence::from_512_second_intervals(9)));
+
+        // Mismatching types never satisfy the check.
+        assert!(!check_relative_locktime(blocks, Sequence::from_512_second_intervals(10)));
+        assert!(!check_relative_locktime(time, Sequence::from_height(10)));
+
+        // Neither does a sequence with the disable flag set.
+        assert!(!check_relative_locktime(blocks, Sequence::MAX));
+    }
+}
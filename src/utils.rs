@@ -12,6 +12,7 @@
 /// This uses an optimized implementation that does not materialize the
 /// actual stack. Instead, it just stores the size of the would-be stack,
 /// and the position of the first false value in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ConditionStack {
     /// The size of the implied stack.
     size: usize,
@@ -35,6 +36,12 @@ impl ConditionStack {
         self.first_false_pos == Self::NO_FALSE
     }
 
+    /// Whether all values except the top one are true, that is whether
+    /// the IF/ELSE/ENDIF of the innermost level is itself in a taken branch.
+    pub fn all_true_below_top(&self) -> bool {
+        self.first_false_pos == Self::NO_FALSE || self.first_false_pos + 1 == self.size
+    }
+
     pub fn push(&mut self, v: bool) {
         if self.first_false_pos == Self::NO_FALSE && !v {
             // The stack consists of all true values, and a false is added.
@@ -31,10 +31,13 @@ pub fn script_hex_to_asm(script_hex: &str) -> Result<String, JsValue> {
 /// - last_opcode: (optional) last opcode run before error produced
 /// - stats: execution runtime statistics with following fields:
 ///   - max_nb_stack_items
+///   - max_nb_altstack_items
 ///   - max_stack_size
 ///   - max_stack_item_size
 ///   - start_validation_weight
 ///   - validation_weight
+///   - gas_spent
+///   - opcode_histogram: map from opcode byte to the number of times it was executed
 #[wasm_bindgen]
 pub fn run_script(script_hex: &str, script_witness: Box<[JsValue]>) -> Result<JsValue, JsValue> {
     console_error_panic_hook::set_once();